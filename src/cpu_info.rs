@@ -1,24 +1,154 @@
-#[cfg(target_os = "windows")]
-mod windows_cpu_info;
+use std::{collections::BTreeMap, fmt};
+
+mod address_widths;
+mod cache;
+mod core_types;
+mod cpu_affinity;
+mod cpu_features;
+mod cpuid_dump;
+mod feature_support;
+mod hypervisor;
 #[cfg(target_os = "linux")]
 mod linux_cpu_info;
-
-#[allow(unused)]
+mod logical_cpus;
+mod microarchitecture;
+mod power_features;
+mod probe_error;
+mod probe_options;
+mod provenance;
+mod security;
+mod snapshot;
+mod tlb;
+mod topology;
+mod tsc;
 #[cfg(target_os = "windows")]
-pub use windows_cpu_info::WindowsCpuInfo;
-#[allow(unused)]
+mod windows_cpu_info;
+mod x86_cpuid;
+mod x86_level;
+
+pub use address_widths::AddressWidths;
+pub use cache::{CacheDescriptor, CacheType};
+pub use core_types::{CoreGroup, CoreType};
+pub use cpu_features::{CpuFeature, CpuFeatures};
+pub use cpuid_dump::{CpuidDump, DumpedCpu};
+pub use feature_support::FeatureSupport;
+pub use hypervisor::{Hypervisor, HypervisorVendor};
 #[cfg(target_os = "linux")]
 pub use linux_cpu_info::LinuxCpuInfo;
+pub use logical_cpus::LogicalCpu;
+pub use microarchitecture::Microarchitecture;
+pub use power_features::POWER_FEATURES;
+pub use probe_error::{ProbeError, ProbeWarning};
+pub use probe_options::ProbeOptions;
+pub use provenance::{Confidence, Provenance, Source};
+pub use security::{EpcSection, MemoryEncryption, SECURITY_FEATURES, Sgx};
+pub use snapshot::{CpuSnapshot, SCHEMA_VERSION};
+pub use tlb::{PageSize, TlbDescriptor, TlbType};
+pub use topology::{TopologyLevel, TopologyNode};
+#[cfg(target_os = "windows")]
+pub use windows_cpu_info::WindowsCpuInfo;
+pub use x86_cpuid::{CpuidDecoder, CpuidResult, CpuidSource, NativeCpuid, Signature};
+pub use x86_level::X86Level;
 
 pub trait CpuInfo {
-    fn new() -> Self;
+    /// Probes with the default options.
+    ///
+    /// # Panics
+    ///
+    /// Panics when probing fails, e.g. when neither CPUID nor `/proc/cpuinfo`
+    /// is available. Use [`CpuInfo::try_new`] to handle the error instead.
+    fn new() -> Self
+    where
+        Self: Sized,
+    {
+        Self::try_new().expect("failed to probe CPU")
+    }
+
+    fn try_new() -> Result<Self, ProbeError>
+    where
+        Self: Sized,
+    {
+        Self::with_options(&ProbeOptions::default())
+    }
+
+    fn with_options(options: &ProbeOptions) -> Result<Self, ProbeError>
+    where
+        Self: Sized;
+
+    /// Starts a [`ProbeOptions`] builder, see there.
+    fn builder() -> ProbeOptions
+    where
+        Self: Sized,
+    {
+        ProbeOptions::new()
+    }
 
     fn static_data(&self) -> &CpuData;
 
+    fn warnings(&self) -> &[ProbeWarning] {
+        self.static_data().warnings()
+    }
+
     fn uptime(&self) -> f64;
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum CpuField {
+    Brand,
+    Vendor,
+    Architecture,
+    Family,
+    Model,
+    Stepping,
+    Hypervisor,
+    AddressWidths,
+    Microcode,
+    Caches,
+    Tlb,
+    Topology,
+    CoreTypes,
+    Flags,
+    XsaveState,
+    ClockSpeed,
+    ClockSpeedTurbo,
+    TscFrequency,
+    Security,
+    LogicalCpus,
+}
+
+impl fmt::Display for CpuField {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            CpuField::Brand => "brand",
+            CpuField::Vendor => "vendor",
+            CpuField::Architecture => "architecture",
+            CpuField::Family => "family",
+            CpuField::Model => "model",
+            CpuField::Stepping => "stepping",
+            CpuField::Hypervisor => "hypervisor",
+            CpuField::AddressWidths => "address widths",
+            CpuField::Microcode => "microcode",
+            CpuField::Caches => "caches",
+            CpuField::Tlb => "TLBs",
+            CpuField::Topology => "topology",
+            CpuField::CoreTypes => "core types",
+            CpuField::Flags => "flags",
+            CpuField::XsaveState => "XSAVE state",
+            CpuField::ClockSpeed => "base clock speed",
+            CpuField::ClockSpeedTurbo => "turbo clock speed",
+            CpuField::TscFrequency => "TSC frequency",
+            CpuField::Security => "security",
+            CpuField::LogicalCpus => "per-CPU table",
+        };
+        write!(f, "{name}")
+    }
+}
+
 #[derive(Debug, Clone, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
 pub struct CpuData {
     brand: String,
     vendor: String,
@@ -26,15 +156,26 @@ pub struct CpuData {
     family: u8,
     model: u8,
     stepping: u8,
+    hypervisor: Option<Hypervisor>,
+    address_widths: AddressWidths,
     microcode: String,
-    l1_cache: usize,
-    l2_cache: usize,
-    l3_cache: usize,
+    caches: Vec<CacheDescriptor>,
+    tlbs: Vec<TlbDescriptor>,
     cores: usize,
     threads: usize,
-    flags: Vec<String>,
+    core_groups: Vec<CoreGroup>,
+    topology: Vec<TopologyNode>,
+    logical_cpus: Vec<LogicalCpu>,
+    flags: CpuFeatures,
+    enabled_state: u64,
+    usable_state: u64,
     clock_speed: u32,
     clock_speed_turbo: u32,
+    tsc_frequency: u64,
+    sgx: Option<Sgx>,
+    memory_encryption: Option<MemoryEncryption>,
+    warnings: Vec<ProbeWarning>,
+    provenance: BTreeMap<CpuField, Provenance>,
 }
 
 impl CpuData {
@@ -62,20 +203,55 @@ impl CpuData {
         self.stepping
     }
 
+    /// Hypervisor the data was probed under, `None` on bare metal and when
+    /// CPUID was not used.
+    pub fn hypervisor(&self) -> Option<&Hypervisor> {
+        self.hypervisor.as_ref()
+    }
+
+    pub fn address_widths(&self) -> &AddressWidths {
+        &self.address_widths
+    }
+
     pub fn microcode_version(&self) -> &String {
         &self.microcode
     }
 
+    /// Every cache instance, ordered by level and type.
+    pub fn caches(&self) -> &[CacheDescriptor] {
+        &self.caches
+    }
+
+    /// Total size in bytes of all caches of a level.
+    pub fn cache_size(&self, level: u8) -> usize {
+        self.caches
+            .iter()
+            .filter(|c| c.level() == level)
+            .map(CacheDescriptor::size)
+            .sum()
+    }
+
+    /// L1 data and instruction caches combined.
     pub fn l1_cache(&self) -> usize {
-        self.l1_cache
+        self.cache_size(1)
     }
 
     pub fn l2_cache(&self) -> usize {
-        self.l2_cache
+        self.cache_size(2)
     }
 
     pub fn l3_cache(&self) -> usize {
-        self.l3_cache
+        self.cache_size(3)
+    }
+
+    /// L4 or eDRAM caches, 0 on most CPUs.
+    pub fn l4_cache(&self) -> usize {
+        self.cache_size(4)
+    }
+
+    /// TLBs of the CPU the data was probed on, ordered by level and type.
+    pub fn tlbs(&self) -> &[TlbDescriptor] {
+        &self.tlbs
     }
 
     pub fn cores(&self) -> usize {
@@ -86,10 +262,54 @@ impl CpuData {
         self.threads
     }
 
-    pub fn flags(&self) -> &Vec<String> {
+    /// Packages with their dies, modules, cores and threads, ordered by
+    /// package ID. Empty when the OS did not give access to every CPU.
+    pub fn topology(&self) -> &[TopologyNode] {
+        &self.topology
+    }
+
+    /// Cores grouped by type on hybrid CPUs, performance cores first. Empty
+    /// on CPUs with a single core type.
+    pub fn core_groups(&self) -> &[CoreGroup] {
+        &self.core_groups
+    }
+
+    pub fn is_hybrid(&self) -> bool {
+        !self.core_groups.is_empty()
+    }
+
+    /// Core type of a logical CPU, `None` on non-hybrid CPUs.
+    pub fn core_type(&self, cpu: usize) -> Option<CoreType> {
+        self.core_groups
+            .iter()
+            .find(|g| g.cpus().contains(&cpu))
+            .map(CoreGroup::core_type)
+    }
+
+    /// What CPUID reports on each logical CPU, ordered by CPU number. Empty
+    /// unless requested with `ProbeOptions::with_per_cpu_table`.
+    pub fn logical_cpus(&self) -> &[LogicalCpu] {
+        &self.logical_cpus
+    }
+
+    pub fn flags(&self) -> &CpuFeatures {
         &self.flags
     }
 
+    pub fn has(&self, feature: CpuFeature) -> bool {
+        self.flags.has(feature)
+    }
+
+    /// XCR0, the extended register state enabled by the OS.
+    pub fn enabled_state(&self) -> u64 {
+        self.enabled_state
+    }
+
+    /// Part of `enabled_state` this process is permitted to use.
+    pub fn usable_state(&self) -> u64 {
+        self.usable_state
+    }
+
     pub fn clock_speed(&self) -> u32 {
         self.clock_speed
     }
@@ -97,4 +317,46 @@ impl CpuData {
     pub fn clock_speed_turbo(&self) -> u32 {
         self.clock_speed_turbo
     }
+
+    /// Rate the time stamp counter ticks at in Hz, 0 when unknown.
+    pub fn tsc_frequency(&self) -> u64 {
+        self.tsc_frequency
+    }
+
+    /// SGX capabilities and EPC sections, `None` without SGX.
+    pub fn sgx(&self) -> Option<&Sgx> {
+        self.sgx.as_ref()
+    }
+
+    /// AMD SME/SEV parameters, `None` without memory encryption.
+    pub fn memory_encryption(&self) -> Option<&MemoryEncryption> {
+        self.memory_encryption.as_ref()
+    }
+
+    pub fn warnings(&self) -> &[ProbeWarning] {
+        &self.warnings
+    }
+
+    pub fn is_known(&self, field: CpuField) -> bool {
+        !self.warnings.iter().any(|w| w.field() == field)
+    }
+
+    pub fn provenance(&self, field: CpuField) -> Option<&Provenance> {
+        self.provenance.get(&field)
+    }
+
+    fn warn(&mut self, field: CpuField, reason: impl Into<String>) {
+        self.warnings.retain(|w| w.field() != field);
+        self.provenance.remove(&field);
+        self.warnings.push(ProbeWarning::new(field, reason));
+    }
+
+    fn record(&mut self, field: CpuField, source: Source, confidence: Confidence) {
+        self.resolve(field, Provenance::new(source, confidence));
+    }
+
+    fn resolve(&mut self, field: CpuField, provenance: Provenance) {
+        self.warnings.retain(|w| w.field() != field);
+        self.provenance.insert(field, provenance);
+    }
 }
@@ -0,0 +1,143 @@
+use crate::cpu_info::{CpuidDecoder, CpuidSource};
+
+/// Physical and linear address sizes of the CPU.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct AddressWidths {
+    pub(crate) physical_bits: u8,
+    pub(crate) linear_bits: u8,
+    pub(crate) guest_physical_bits: u8,
+    pub(crate) encryption_reduction: u8,
+    pub(crate) la57: bool,
+}
+
+impl AddressWidths {
+    pub fn physical_bits(&self) -> u8 {
+        self.physical_bits
+    }
+
+    pub fn linear_bits(&self) -> u8 {
+        self.linear_bits
+    }
+
+    /// Physical address size for guests of this CPU, 0 when it is the same
+    /// as `physical_bits` or not reported.
+    pub fn guest_physical_bits(&self) -> u8 {
+        self.guest_physical_bits
+    }
+
+    /// Physical address bits lost while AMD memory encryption (SME/SEV) is
+    /// enabled, from leaf 0x8000001F.
+    pub fn encryption_reduction(&self) -> u8 {
+        self.encryption_reduction
+    }
+
+    /// Whether the CPU supports 5-level paging and 57-bit linear addresses.
+    pub fn la57(&self) -> bool {
+        self.la57
+    }
+
+    /// Largest physical address space in bytes, without memory encryption.
+    pub fn max_physical_memory(&self) -> u64 {
+        1u64.checked_shl(self.physical_bits as u32).unwrap_or(0)
+    }
+
+    /// Largest physical address space in bytes with memory encryption
+    /// enabled.
+    pub fn max_encrypted_memory(&self) -> u64 {
+        let bits = self.physical_bits.saturating_sub(self.encryption_reduction);
+        1u64.checked_shl(bits as u32).unwrap_or(0)
+    }
+}
+
+impl<S: CpuidSource + ?Sized> CpuidDecoder<'_, S> {
+    /// Address sizes from leaf 0x80000008. CPUs without it address 36 bits
+    /// with PAE and 32 bits otherwise; the returned flag is `false` then.
+    pub fn address_widths(&self) -> (AddressWidths, bool) {
+        let la57 = self.leaf(7, 0).unwrap_or_default().ecx & (1 << 16) != 0;
+        let encryption_reduction = self
+            .leaf(0x8000_001F, 0)
+            .filter(|r| r.eax & 0b11 != 0)
+            .map_or(0, |r| ((r.ebx >> 6) & 0x3F) as u8);
+
+        match self.leaf(0x8000_0008, 0) {
+            Some(r) if r.eax & 0xFF != 0 => (
+                AddressWidths {
+                    physical_bits: (r.eax & 0xFF) as u8,
+                    linear_bits: ((r.eax >> 8) & 0xFF) as u8,
+                    guest_physical_bits: ((r.eax >> 16) & 0xFF) as u8,
+                    encryption_reduction,
+                    la57,
+                },
+                true,
+            ),
+            _ => {
+                let pae = self.leaf(1, 0).unwrap_or_default().edx & (1 << 6) != 0;
+                (
+                    AddressWidths {
+                        physical_bits: if pae { 36 } else { 32 },
+                        linear_bits: 32,
+                        la57,
+                        ..Default::default()
+                    },
+                    false,
+                )
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cpu_info::x86_cpuid::tests::table;
+
+    fn amd(leaves: &[(u32, u32, [u32; 4])]) -> (AddressWidths, bool) {
+        let mut leaves = leaves.to_vec();
+        leaves.push((0, 0, [1, 0, 0, 0]));
+        leaves.push((0x8000_0000, 0, [0x8000_001F, 0, 0, 0]));
+        CpuidDecoder::new(&table(&leaves)).address_widths()
+    }
+
+    #[test]
+    fn guest_physical_override() {
+        //48 physical, 57 linear, guests limited to 40 bits
+        let (widths, reported) = amd(&[(0x8000_0008, 0, [0x0028_3930, 0, 0, 0])]);
+        assert!(reported);
+        assert_eq!(
+            (
+                widths.physical_bits(),
+                widths.linear_bits(),
+                widths.guest_physical_bits()
+            ),
+            (48, 57, 40)
+        );
+        assert_eq!(widths.max_physical_memory(), 1 << 48);
+    }
+
+    #[test]
+    fn encryption_reduction() {
+        //SME and SEV supported, 5 physical address bits lost while enabled
+        let (widths, _) = amd(&[
+            (0x8000_0008, 0, [0x0000_3030, 0, 0, 0]),
+            (0x8000_001F, 0, [0b11, (5 << 6) | 51, 0, 0]),
+        ]);
+        assert_eq!(widths.encryption_reduction(), 5);
+        assert_eq!(widths.max_encrypted_memory(), 1 << 43);
+
+        //The field means nothing without SME or SEV
+        let (widths, _) = amd(&[
+            (0x8000_0008, 0, [0x0000_3030, 0, 0, 0]),
+            (0x8000_001F, 0, [0, (5 << 6) | 51, 0, 0]),
+        ]);
+        assert_eq!(widths.encryption_reduction(), 0);
+        assert_eq!(widths.max_encrypted_memory(), 1 << 48);
+    }
+
+    #[test]
+    fn without_leaf_0x80000008() {
+        let (widths, reported) = amd(&[(1, 0, [0, 0, 0, 1 << 6])]);
+        assert!(!reported);
+        assert_eq!((widths.physical_bits(), widths.linear_bits()), (36, 32));
+    }
+}
@@ -0,0 +1,166 @@
+use std::{collections::BTreeMap, fmt};
+
+use crate::cpu_info::{Confidence, CpuData, CpuField, CpuidDecoder, ProbeOptions, Source};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum CacheType {
+    Data,
+    Instruction,
+    Unified,
+}
+
+impl fmt::Display for CacheType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            CacheType::Data => "data",
+            CacheType::Instruction => "instruction",
+            CacheType::Unified => "unified",
+        };
+        write!(f, "{name}")
+    }
+}
+
+/// One cache instance and the logical CPUs sharing it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct CacheDescriptor {
+    pub(crate) level: u8,
+    pub(crate) cache_type: CacheType,
+    pub(crate) size: usize,
+    pub(crate) ways: u32,
+    pub(crate) line_size: u32,
+    pub(crate) sets: u32,
+    pub(crate) partitions: u32,
+    pub(crate) inclusive: Option<bool>,
+    pub(crate) shared_cpus: Vec<usize>,
+}
+
+impl CacheDescriptor {
+    pub fn level(&self) -> u8 {
+        self.level
+    }
+
+    pub fn cache_type(&self) -> CacheType {
+        self.cache_type
+    }
+
+    /// Size in bytes.
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    /// Associativity, 0 for a fully associative cache.
+    pub fn ways(&self) -> u32 {
+        self.ways
+    }
+
+    pub fn line_size(&self) -> u32 {
+        self.line_size
+    }
+
+    pub fn sets(&self) -> u32 {
+        self.sets
+    }
+
+    pub fn partitions(&self) -> u32 {
+        self.partitions
+    }
+
+    /// Whether the cache includes the lower levels, `None` when unknown.
+    pub fn inclusive(&self) -> Option<bool> {
+        self.inclusive
+    }
+
+    /// Logical CPUs sharing this instance.
+    pub fn shared_cpus(&self) -> &[usize] {
+        &self.shared_cpus
+    }
+}
+
+/// Cache parameters one logical CPU reports through CPUID, before its
+/// instances are known.
+#[derive(Debug, Clone)]
+pub(crate) struct CacheParameters {
+    pub(crate) descriptor: CacheDescriptor,
+    /// APIC ID bits below which CPUs share the cache.
+    pub(crate) sharing_shift: u32,
+}
+
+/// Turns per-CPU cache parameters into instances. CPUs whose APIC IDs only
+/// differ below a cache's sharing shift share that cache. Caches reported with
+/// different sharing shifts, as by different core types, are kept apart.
+pub(crate) fn cache_instances<'a>(
+    cpus: impl IntoIterator<Item = (usize, u32, &'a [CacheParameters])>,
+) -> Vec<CacheDescriptor> {
+    let mut instances: BTreeMap<(u8, CacheType, u32, u32), CacheDescriptor> = BTreeMap::new();
+    for (cpu, apic_id, caches) in cpus {
+        for cache in caches {
+            let d = &cache.descriptor;
+            let shift = cache.sharing_shift;
+            instances
+                .entry((d.level, d.cache_type, shift, apic_id >> shift))
+                .or_insert_with(|| d.clone())
+                .shared_cpus
+                .push(cpu);
+        }
+    }
+    let mut instances: Vec<CacheDescriptor> = instances.into_values().collect();
+    instances.sort_by(|a, b| {
+        (a.level, a.cache_type, &a.shared_cpus).cmp(&(b.level, b.cache_type, &b.shared_cpus))
+    });
+    instances
+}
+
+/// Cache instances seen from CPUID on every logical CPU.
+pub(crate) fn cpuid_caches(options: &ProbeOptions) -> Vec<CacheDescriptor> {
+    let cpus = options.per_cpu(|cpu, source| {
+        let decoder = CpuidDecoder::new(source);
+        (cpu, decoder.apic_id(), decoder.cache_parameters())
+    });
+    cache_instances(
+        cpus.iter()
+            .map(|(cpu, apic_id, caches)| (*cpu, *apic_id, caches.as_slice())),
+    )
+}
+
+impl CpuData {
+    /// Replaces the cache instances with ones the OS reports, keeping the
+    /// inclusivity only CPUID knows about.
+    pub(crate) fn set_os_caches(&mut self, mut caches: Vec<CacheDescriptor>, source: Source) {
+        for cache in &mut caches {
+            cache.inclusive = cache.inclusive.or_else(|| {
+                self.caches
+                    .iter()
+                    .find(|c| c.level == cache.level && c.cache_type == cache.cache_type)
+                    .and_then(|c| c.inclusive)
+            });
+        }
+        self.caches = caches;
+        self.record(CpuField::Caches, source, Confidence::High);
+    }
+
+    /// Data or unified cache of a level that a logical CPU uses, e.g. the L3
+    /// slice of a CCX on AMD. Its `shared_cpus` are the CPUs sharing it.
+    pub fn cache_of(&self, cpu: usize, level: u8) -> Option<&CacheDescriptor> {
+        self.caches
+            .iter()
+            .filter(|c| c.level == level && c.cache_type != CacheType::Instruction)
+            .find(|c| c.shared_cpus.contains(&cpu))
+    }
+
+    /// Replaces cache instances guessed from the leaves of one CPU with the
+    /// ones seen on every CPU. Caches the OS reported are kept.
+    pub(crate) fn set_cpuid_caches(&mut self, caches: Vec<CacheDescriptor>) {
+        let Some(Source::Cpuid { leaf }) = self.provenance(CpuField::Caches).map(|p| p.source())
+        else {
+            return;
+        };
+        if !caches.is_empty() {
+            let leaf = *leaf;
+            self.caches = caches;
+            self.record(CpuField::Caches, Source::Cpuid { leaf }, Confidence::High);
+        }
+    }
+}
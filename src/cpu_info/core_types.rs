@@ -0,0 +1,179 @@
+use std::{collections::BTreeSet, fmt};
+
+use crate::cpu_info::{
+    CacheDescriptor, CpuData, CpuidDecoder, CpuidSource, ProbeOptions,
+    cache::{CacheParameters, cache_instances},
+};
+
+/// Core type of a logical CPU on hybrid CPUs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum CoreType {
+    Performance,
+    Efficiency,
+}
+
+impl CoreType {
+    /// "P" or "E".
+    pub fn letter(self) -> char {
+        match self {
+            CoreType::Performance => 'P',
+            CoreType::Efficiency => 'E',
+        }
+    }
+}
+
+impl fmt::Display for CoreType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            CoreType::Performance => "performance",
+            CoreType::Efficiency => "efficiency",
+        };
+        write!(f, "{name}")
+    }
+}
+
+/// Cores of one type on a hybrid CPU, with the caches and clocks of that
+/// type. Cache sizes are totals over all cores of the group.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct CoreGroup {
+    pub(crate) core_type: CoreType,
+    pub(crate) cpus: Vec<usize>,
+    pub(crate) cores: usize,
+    pub(crate) l1_cache: usize,
+    pub(crate) l2_cache: usize,
+    pub(crate) clock_speed: u32,
+    pub(crate) clock_speed_turbo: u32,
+}
+
+impl CoreGroup {
+    pub fn core_type(&self) -> CoreType {
+        self.core_type
+    }
+
+    /// Logical CPU numbers of this type.
+    pub fn cpus(&self) -> &[usize] {
+        &self.cpus
+    }
+
+    pub fn cores(&self) -> usize {
+        self.cores
+    }
+
+    pub fn threads(&self) -> usize {
+        self.cpus.len()
+    }
+
+    pub fn l1_cache(&self) -> usize {
+        self.l1_cache
+    }
+
+    pub fn l2_cache(&self) -> usize {
+        self.l2_cache
+    }
+
+    /// Base clock speed in MHz, 0 when unknown.
+    pub fn clock_speed(&self) -> u32 {
+        self.clock_speed
+    }
+
+    /// Maximum clock speed in MHz, 0 when unknown.
+    pub fn clock_speed_turbo(&self) -> u32 {
+        self.clock_speed_turbo
+    }
+}
+
+/// Topology and caches one logical CPU reports about itself through CPUID.
+pub(crate) struct CoreSample {
+    cpu: usize,
+    core_type: Option<CoreType>,
+    apic_id: u32,
+    smt_shift: u32,
+    caches: Vec<CacheParameters>,
+}
+
+impl CoreSample {
+    /// Must run on the CPU it describes.
+    pub(crate) fn describe<S: CpuidSource + ?Sized>(cpu: usize, source: &S) -> Self {
+        let decoder = CpuidDecoder::new(source);
+        Self {
+            cpu,
+            core_type: decoder.core_type(),
+            apic_id: decoder.apic_id(),
+            smt_shift: decoder.smt_shift(),
+            caches: decoder.cache_parameters(),
+        }
+    }
+}
+
+/// Core groups and the cache instances seen from CPUID on every logical CPU.
+/// Threads are only pinned when the CPU reports itself as hybrid.
+pub(crate) fn cpuid_core_groups(options: &ProbeOptions) -> (Vec<CoreGroup>, Vec<CacheDescriptor>) {
+    let hybrid = options
+        .cpuid_source()
+        .is_some_and(|source| CpuidDecoder::new(&*source).is_hybrid());
+    if !hybrid {
+        return (Vec::new(), Vec::new());
+    }
+    let cpus = options.per_cpu(|cpu, source| CoreSample::describe(cpu, source));
+    let caches = cache_instances(cpus.iter().map(|c| (c.cpu, c.apic_id, c.caches.as_slice())));
+    (group_by_core_type(&cpus), caches)
+}
+
+/// Groups logical CPUs by core type. Empty unless more than one type is
+/// present.
+fn group_by_core_type(cpus: &[CoreSample]) -> Vec<CoreGroup> {
+    let mut groups = Vec::new();
+    for core_type in [CoreType::Performance, CoreType::Efficiency] {
+        let members: Vec<&CoreSample> = cpus
+            .iter()
+            .filter(|c| c.core_type == Some(core_type))
+            .collect();
+        if members.is_empty() {
+            continue;
+        }
+
+        let cores: BTreeSet<u32> = members.iter().map(|c| c.apic_id >> c.smt_shift).collect();
+        groups.push(CoreGroup {
+            core_type,
+            cpus: members.iter().map(|c| c.cpu).collect(),
+            cores: cores.len(),
+            l1_cache: 0,
+            l2_cache: 0,
+            clock_speed: 0,
+            clock_speed_turbo: 0,
+        });
+    }
+    if groups.len() < 2 {
+        groups.clear();
+    }
+    groups
+}
+
+impl CpuData {
+    /// Replaces core and thread totals with the sums over the groups, and
+    /// sizes each group's L1 and L2 from the cache instances its CPUs own.
+    pub(crate) fn set_core_groups(&mut self, mut groups: Vec<CoreGroup>) {
+        if groups.is_empty() {
+            return;
+        }
+        self.cores = groups.iter().map(CoreGroup::cores).sum();
+        self.threads = groups.iter().map(CoreGroup::threads).sum();
+        for group in &mut groups {
+            let size = |level: u8| -> usize {
+                self.caches
+                    .iter()
+                    .filter(|c| c.level() == level)
+                    .filter(|c| c.shared_cpus().iter().all(|cpu| group.cpus.contains(cpu)))
+                    .map(CacheDescriptor::size)
+                    .sum()
+            };
+            let (l1_cache, l2_cache) = (size(1), size(2));
+            group.l1_cache = l1_cache;
+            group.l2_cache = l2_cache;
+        }
+        self.core_groups = groups;
+    }
+}
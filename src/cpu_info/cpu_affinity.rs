@@ -0,0 +1,76 @@
+use std::thread;
+
+/// Logical CPUs the OS reports as online, in ascending order.
+pub fn online_cpus() -> Vec<usize> {
+    #[cfg(target_os = "linux")]
+    if let Ok(s) = std::fs::read_to_string("/sys/devices/system/cpu/online") {
+        let cpus = parse_cpu_list(&s);
+        if !cpus.is_empty() {
+            return cpus;
+        }
+    }
+
+    let count = thread::available_parallelism().map_or(1, |n| n.get());
+    (0..count).collect()
+}
+
+/// Runs `f` on a helper thread pinned to the given logical CPU. Returns `None`
+/// when the thread could not be pinned, so results never come from a
+/// different CPU than requested.
+pub fn run_on_cpu<T, F>(cpu: usize, f: F) -> Option<T>
+where
+    T: Send,
+    F: FnOnce() -> T + Send,
+{
+    thread::scope(|scope| {
+        scope
+            .spawn(move || pin_current_thread(cpu).then(f))
+            .join()
+            .ok()
+            .flatten()
+    })
+}
+
+#[cfg(target_os = "linux")]
+fn pin_current_thread(cpu: usize) -> bool {
+    if cpu >= libc::CPU_SETSIZE as usize {
+        return false;
+    }
+    unsafe {
+        let mut set: libc::cpu_set_t = std::mem::zeroed();
+        libc::CPU_ZERO(&mut set);
+        libc::CPU_SET(cpu, &mut set);
+        libc::sched_setaffinity(0, size_of::<libc::cpu_set_t>(), &set) == 0
+    }
+}
+
+#[cfg(target_os = "windows")]
+fn pin_current_thread(cpu: usize) -> bool {
+    use windows::Win32::System::Threading::{GetCurrentThread, SetThreadAffinityMask};
+
+    if cpu >= usize::BITS as usize {
+        return false;
+    }
+    unsafe { SetThreadAffinityMask(GetCurrentThread(), 1 << cpu) != 0 }
+}
+
+/// Parses kernel CPU lists such as `0-3,8,10-11`.
+#[cfg(target_os = "linux")]
+pub fn parse_cpu_list(list: &str) -> Vec<usize> {
+    let mut cpus = Vec::new();
+    for part in list.trim().split(',').filter(|p| !p.is_empty()) {
+        match part.split_once('-') {
+            Some((start, end)) => {
+                if let (Ok(start), Ok(end)) = (start.parse::<usize>(), end.parse::<usize>()) {
+                    cpus.extend(start..=end);
+                }
+            }
+            None => {
+                if let Ok(cpu) = part.parse::<usize>() {
+                    cpus.push(cpu);
+                }
+            }
+        }
+    }
+    cpus
+}
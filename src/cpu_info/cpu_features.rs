@@ -0,0 +1,553 @@
+use std::{
+    fmt,
+    ops::{BitAnd, BitOr, Sub},
+};
+
+macro_rules! cpu_features {
+    ($($variant:ident => $name:literal,)*) => {
+        /// Single CPU feature, named after its `/proc/cpuinfo` flag.
+        #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+        #[repr(u16)]
+        pub enum CpuFeature {
+            $($variant,)*
+        }
+
+        impl CpuFeature {
+            pub const ALL: &'static [CpuFeature] = &[$(CpuFeature::$variant,)*];
+
+            pub fn name(self) -> &'static str {
+                match self {
+                    $(CpuFeature::$variant => $name,)*
+                }
+            }
+
+            pub fn from_name(name: &str) -> Option<Self> {
+                match name {
+                    $($name => Some(CpuFeature::$variant),)*
+                    _ => None,
+                }
+            }
+        }
+    };
+}
+
+cpu_features! {
+    //Leaf 0x1 EDX
+    Fpu => "fpu",
+    Vme => "vme",
+    De => "de",
+    Pse => "pse",
+    Tsc => "tsc",
+    Msr => "msr",
+    Pae => "pae",
+    Mce => "mce",
+    Cx8 => "cx8",
+    Apic => "apic",
+    Sep => "sep",
+    Mtrr => "mtrr",
+    Pge => "pge",
+    Mca => "mca",
+    Cmov => "cmov",
+    Pat => "pat",
+    Pse36 => "pse36",
+    Pn => "pn",
+    Clflush => "clflush",
+    Dts => "dts",
+    Acpi => "acpi",
+    Mmx => "mmx",
+    Fxsr => "fxsr",
+    Sse => "sse",
+    Sse2 => "sse2",
+    Ss => "ss",
+    Ht => "ht",
+    Tm => "tm",
+    Ia64 => "ia64",
+    Pbe => "pbe",
+    //Leaf 0x1 ECX
+    Sse3 => "pni",
+    Pclmulqdq => "pclmulqdq",
+    Dtes64 => "dtes64",
+    Monitor => "monitor",
+    DsCpl => "ds_cpl",
+    Vmx => "vmx",
+    Smx => "smx",
+    Est => "est",
+    Tm2 => "tm2",
+    Ssse3 => "ssse3",
+    Cid => "cid",
+    Sdbg => "sdbg",
+    Fma => "fma",
+    Cx16 => "cx16",
+    Xtpr => "xtpr",
+    Pdcm => "pdcm",
+    Pcid => "pcid",
+    Dca => "dca",
+    Sse4_1 => "sse4_1",
+    Sse4_2 => "sse4_2",
+    X2apic => "x2apic",
+    Movbe => "movbe",
+    Popcnt => "popcnt",
+    TscDeadlineTimer => "tsc_deadline_timer",
+    Aes => "aes",
+    Xsave => "xsave",
+    Osxsave => "osxsave",
+    Avx => "avx",
+    F16c => "f16c",
+    Rdrand => "rdrand",
+    Hypervisor => "hypervisor",
+    //Leaf 0x80000001 EDX
+    Syscall => "syscall",
+    Mp => "mp",
+    Nx => "nx",
+    Mmxext => "mmxext",
+    FxsrOpt => "fxsr_opt",
+    Pdpe1gb => "pdpe1gb",
+    Rdtscp => "rdtscp",
+    Lm => "lm",
+    ThreeDNowExt => "3dnowext",
+    ThreeDNow => "3dnow",
+    //Leaf 0x80000001 ECX
+    LahfLm => "lahf_lm",
+    CmpLegacy => "cmp_legacy",
+    Svm => "svm",
+    Extapic => "extapic",
+    Cr8Legacy => "cr8_legacy",
+    Abm => "abm",
+    Sse4a => "sse4a",
+    Misalignsse => "misalignsse",
+    ThreeDNowPrefetch => "3dnowprefetch",
+    Osvw => "osvw",
+    Ibs => "ibs",
+    Xop => "xop",
+    Skinit => "skinit",
+    Wdt => "wdt",
+    Lwp => "lwp",
+    Fma4 => "fma4",
+    Tce => "tce",
+    NodeidMsr => "nodeid_msr",
+    Tbm => "tbm",
+    Topoext => "topoext",
+    PerfctrCore => "perfctr_core",
+    PerfctrNb => "perfctr_nb",
+    Bpext => "bpext",
+    Ptsc => "ptsc",
+    PerfctrLlc => "perfctr_llc",
+    Mwaitx => "mwaitx",
+    //Leaf 0x80000007 EDX, advanced power management
+    Ts => "ts",
+    Fid => "fid",
+    Vid => "vid",
+    Ttp => "ttp",
+    Htc => "htc",
+    Stc => "stc",
+    Steps100Mhz => "100mhzsteps",
+    HwPstate => "hw_pstate",
+    ConstantTsc => "constant_tsc",
+    Cpb => "cpb",
+    EffFreqRo => "eff_freq_ro",
+    ProcFeedback => "proc_feedback",
+    AccPower => "acc_power",
+    //Leaf 0x6 EAX, thermal and power management
+    Dtherm => "dtherm",
+    Ida => "ida",
+    Arat => "arat",
+    Pln => "pln",
+    Pts => "pts",
+    Hwp => "hwp",
+    HwpNotify => "hwp_notify",
+    HwpActWindow => "hwp_act_window",
+    HwpEpp => "hwp_epp",
+    HwpPkgReq => "hwp_pkg_req",
+    HwpHighestPerfChange => "hwp_highest_perf_change",
+    Hfi => "hfi",
+    //Leaf 0x6 ECX
+    Aperfmperf => "aperfmperf",
+    Epb => "epb",
+    //Leaf 0x7 subleaf 0 EBX
+    Fsgsbase => "fsgsbase",
+    TscAdjust => "tsc_adjust",
+    Sgx => "sgx",
+    Bmi1 => "bmi1",
+    Hle => "hle",
+    Avx2 => "avx2",
+    FdpExcptnOnly => "fdp_excptn_only",
+    Smep => "smep",
+    Bmi2 => "bmi2",
+    Erms => "erms",
+    Invpcid => "invpcid",
+    Rtm => "rtm",
+    Cqm => "cqm",
+    ZeroFcsFds => "zero_fcs_fds",
+    Mpx => "mpx",
+    RdtA => "rdt_a",
+    Avx512f => "avx512f",
+    Avx512dq => "avx512dq",
+    Rdseed => "rdseed",
+    Adx => "adx",
+    Smap => "smap",
+    Avx512ifma => "avx512ifma",
+    Clflushopt => "clflushopt",
+    Clwb => "clwb",
+    IntelPt => "intel_pt",
+    Avx512pf => "avx512pf",
+    Avx512er => "avx512er",
+    Avx512cd => "avx512cd",
+    ShaNi => "sha_ni",
+    Avx512bw => "avx512bw",
+    Avx512vl => "avx512vl",
+    //Leaf 0x7 subleaf 0 ECX
+    Prefetchwt1 => "prefetchwt1",
+    Avx512vbmi => "avx512vbmi",
+    Umip => "umip",
+    Pku => "pku",
+    Ospke => "ospke",
+    Waitpkg => "waitpkg",
+    Avx512Vbmi2 => "avx512_vbmi2",
+    Shstk => "shstk",
+    Gfni => "gfni",
+    Vaes => "vaes",
+    Vpclmulqdq => "vpclmulqdq",
+    Avx512Vnni => "avx512_vnni",
+    Avx512Bitalg => "avx512_bitalg",
+    Tme => "tme",
+    Avx512Vpopcntdq => "avx512_vpopcntdq",
+    La57 => "la57",
+    Rdpid => "rdpid",
+    Kl => "kl",
+    BusLockDetect => "bus_lock_detect",
+    Cldemote => "cldemote",
+    Movdiri => "movdiri",
+    Movdir64b => "movdir64b",
+    Enqcmd => "enqcmd",
+    SgxLc => "sgx_lc",
+    Pks => "pks",
+    //Leaf 0x7 subleaf 0 EDX
+    Avx512_4vnniw => "avx512_4vnniw",
+    Avx512_4fmaps => "avx512_4fmaps",
+    Fsrm => "fsrm",
+    Avx512Vp2intersect => "avx512_vp2intersect",
+    SrbdsCtrl => "srbds_ctrl",
+    MdClear => "md_clear",
+    RtmAlwaysAbort => "rtm_always_abort",
+    TsxForceAbort => "tsx_force_abort",
+    Serialize => "serialize",
+    HybridCpu => "hybrid_cpu",
+    Tsxldtrk => "tsxldtrk",
+    Pconfig => "pconfig",
+    ArchLbr => "arch_lbr",
+    Ibt => "ibt",
+    AmxBf16 => "amx_bf16",
+    Avx512Fp16 => "avx512_fp16",
+    AmxTile => "amx_tile",
+    AmxInt8 => "amx_int8",
+    SpecCtrl => "spec_ctrl",
+    IntelStibp => "intel_stibp",
+    FlushL1d => "flush_l1d",
+    ArchCapabilities => "arch_capabilities",
+    CoreCapabilities => "core_capabilities",
+    SpecCtrlSsbd => "spec_ctrl_ssbd",
+    //Leaf 0x7 subleaf 1 EAX
+    Sha512 => "sha512",
+    Sm3 => "sm3",
+    Sm4 => "sm4",
+    AvxVnni => "avx_vnni",
+    Avx512Bf16 => "avx512_bf16",
+    Cmpccxadd => "cmpccxadd",
+    Fzrm => "fzrm",
+    Fsrs => "fsrs",
+    Fsrc => "fsrc",
+    Fred => "fred",
+    Lkgs => "lkgs",
+    Wrmsrns => "wrmsrns",
+    AmxFp16 => "amx_fp16",
+    Hreset => "hreset",
+    AvxIfma => "avx_ifma",
+    Lam => "lam",
+    Msrlist => "msrlist",
+    //Leaf 0x7 subleaf 1 EDX
+    AvxVnniInt8 => "avx_vnni_int8",
+    AvxNeConvert => "avx_ne_convert",
+    AmxComplex => "amx_complex",
+    AvxVnniInt16 => "avx_vnni_int16",
+    Prefetchiti => "prefetchiti",
+    CetSss => "cet_sss",
+    Avx10 => "avx10",
+    ApxF => "apx_f",
+    //Leaf 0x7 subleaf 2 EDX
+    Psfd => "psfd",
+    IpredCtrl => "ipred_ctrl",
+    RrsbaCtrl => "rrsba_ctrl",
+    DdpdU => "ddpd_u",
+    BhiCtrl => "bhi_ctrl",
+    McdtNo => "mcdt_no",
+    //Leaf 0xD subleaf 1 EAX
+    Xsaveopt => "xsaveopt",
+    Xsavec => "xsavec",
+    Xgetbv1 => "xgetbv1",
+    Xsaves => "xsaves",
+    Xfd => "xfd",
+    //Leaf 0x14 subleaf 0 EBX, processor trace
+    PtCr3Filtering => "pt_cr3_filtering",
+    PtPsbCyc => "pt_psb_cyc",
+    PtIpFiltering => "pt_ip_filtering",
+    PtMtc => "pt_mtc",
+    PtPtwrite => "pt_ptwrite",
+    PtPowerEventTrace => "pt_power_event_trace",
+    PtPsbPmiPreservation => "pt_psb_pmi_preservation",
+    PtEventTrace => "pt_event_trace",
+    PtTntDisable => "pt_tnt_disable",
+    //Leaf 0x14 subleaf 0 ECX
+    PtTopa => "pt_topa",
+    PtTopaMultiple => "pt_topa_multiple",
+    PtSingleRange => "pt_single_range",
+    PtTraceTransport => "pt_trace_transport",
+    PtLip => "pt_lip",
+    //Leaf 0x19 EBX, key locker
+    Aeskle => "aeskle",
+    WideKl => "wide_kl",
+    KlIwkeyBackup => "kl_iwkey_backup",
+    //Leaf 0x21, Intel TDX guest
+    TdxGuest => "tdx_guest",
+    //Leaf 0x80000008 EBX
+    Clzero => "clzero",
+    Irperf => "irperf",
+    Xsaveerptr => "xsaveerptr",
+    Rdpru => "rdpru",
+    Mcommit => "mcommit",
+    Wbnoinvd => "wbnoinvd",
+    AmdIbpb => "amd_ibpb",
+    AmdIbrs => "amd_ibrs",
+    AmdStibp => "amd_stibp",
+    AmdStibpAlwaysOn => "amd_stibp_always_on",
+    AmdPpin => "amd_ppin",
+    AmdSsbd => "amd_ssbd",
+    VirtSsbd => "virt_ssbd",
+    AmdSsbNo => "amd_ssb_no",
+    Cppc => "cppc",
+    AmdPsfd => "amd_psfd",
+    BtcNo => "btc_no",
+    AmdIbpbRet => "amd_ibpb_ret",
+    //Leaf 0x8000001F EAX, memory encryption
+    Sme => "sme",
+    Sev => "sev",
+    VmPageFlush => "vm_page_flush",
+    SevEs => "sev_es",
+    SevSnp => "sev_snp",
+    VTscAux => "v_tsc_aux",
+    SmeCoherent => "sme_coherent",
+    DebugSwap => "debug_swap",
+}
+
+impl fmt::Display for CpuFeature {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+const WORDS: usize = CpuFeature::ALL.len().div_ceil(64);
+
+/// Set of `CpuFeature`s stored as a bitset.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct CpuFeatures {
+    bits: [u64; WORDS],
+}
+
+impl CpuFeatures {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn has(&self, feature: CpuFeature) -> bool {
+        let index = feature as usize;
+        self.bits[index / 64] & (1 << (index % 64)) != 0
+    }
+
+    pub fn insert(&mut self, feature: CpuFeature) {
+        let index = feature as usize;
+        self.bits[index / 64] |= 1 << (index % 64);
+    }
+
+    pub fn remove(&mut self, feature: CpuFeature) {
+        let index = feature as usize;
+        self.bits[index / 64] &= !(1 << (index % 64));
+    }
+
+    pub fn set(&mut self, feature: CpuFeature, value: bool) {
+        if value {
+            self.insert(feature);
+        } else {
+            self.remove(feature);
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.bits.iter().map(|w| w.count_ones() as usize).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.bits.iter().all(|&w| w == 0)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = CpuFeature> + '_ {
+        CpuFeature::ALL.iter().copied().filter(|&f| self.has(f))
+    }
+
+    pub fn union(&self, other: &CpuFeatures) -> CpuFeatures {
+        self.zip_with(other, |a, b| a | b)
+    }
+
+    pub fn intersection(&self, other: &CpuFeatures) -> CpuFeatures {
+        self.zip_with(other, |a, b| a & b)
+    }
+
+    pub fn difference(&self, other: &CpuFeatures) -> CpuFeatures {
+        self.zip_with(other, |a, b| a & !b)
+    }
+
+    pub fn is_subset(&self, other: &CpuFeatures) -> bool {
+        self.difference(other).is_empty()
+    }
+
+    fn zip_with(&self, other: &CpuFeatures, op: impl Fn(u64, u64) -> u64) -> CpuFeatures {
+        let mut bits = [0; WORDS];
+        for (i, word) in bits.iter_mut().enumerate() {
+            *word = op(self.bits[i], other.bits[i]);
+        }
+        CpuFeatures { bits }
+    }
+}
+
+impl BitOr for CpuFeatures {
+    type Output = CpuFeatures;
+
+    fn bitor(self, rhs: Self) -> Self::Output {
+        self.union(&rhs)
+    }
+}
+
+impl BitAnd for CpuFeatures {
+    type Output = CpuFeatures;
+
+    fn bitand(self, rhs: Self) -> Self::Output {
+        self.intersection(&rhs)
+    }
+}
+
+impl Sub for CpuFeatures {
+    type Output = CpuFeatures;
+
+    fn sub(self, rhs: Self) -> Self::Output {
+        self.difference(&rhs)
+    }
+}
+
+impl FromIterator<CpuFeature> for CpuFeatures {
+    fn from_iter<T: IntoIterator<Item = CpuFeature>>(iter: T) -> Self {
+        let mut features = CpuFeatures::new();
+        features.extend(iter);
+        features
+    }
+}
+
+impl Extend<CpuFeature> for CpuFeatures {
+    fn extend<T: IntoIterator<Item = CpuFeature>>(&mut self, iter: T) {
+        for feature in iter {
+            self.insert(feature);
+        }
+    }
+}
+
+impl fmt::Display for CpuFeatures {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let names: Vec<&str> = self.iter().map(CpuFeature::name).collect();
+        write!(f, "{}", names.join(" "))
+    }
+}
+
+#[cfg(feature = "serde")]
+impl serde::Serialize for CpuFeature {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.name())
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for CpuFeature {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let name = String::deserialize(deserializer)?;
+        CpuFeature::from_name(&name)
+            .ok_or_else(|| serde::de::Error::custom(format!("unknown CPU feature \"{name}\"")))
+    }
+}
+
+// Serialized as a list of flag names. Names unknown to this version of the
+// crate are skipped so snapshots taken by newer versions still load.
+#[cfg(feature = "serde")]
+impl serde::Serialize for CpuFeatures {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(self.iter().map(CpuFeature::name))
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for CpuFeatures {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let names = Vec::<String>::deserialize(deserializer)?;
+        Ok(names
+            .iter()
+            .filter_map(|name| CpuFeature::from_name(name))
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn set(features: &[CpuFeature]) -> CpuFeatures {
+        features.iter().copied().collect()
+    }
+
+    #[test]
+    fn names_round_trip() {
+        for &feature in CpuFeature::ALL {
+            assert_eq!(CpuFeature::from_name(feature.name()), Some(feature));
+        }
+        assert_eq!(CpuFeature::from_name("not_a_flag"), None);
+    }
+
+    #[test]
+    fn insert_and_remove_across_words() {
+        let last = CpuFeature::ALL[CpuFeature::ALL.len() - 1];
+        let mut features = CpuFeatures::new();
+        features.insert(CpuFeature::Fpu);
+        features.insert(last);
+        assert!(features.has(CpuFeature::Fpu) && features.has(last));
+        assert_eq!(features.len(), 2);
+
+        features.remove(CpuFeature::Fpu);
+        features.set(last, false);
+        assert!(features.is_empty());
+    }
+
+    #[test]
+    fn set_operators() {
+        use CpuFeature::*;
+        let a = set(&[Sse2, Avx, Avx2]);
+        let b = set(&[Avx2, Avx512f]);
+        assert_eq!(a | b, set(&[Sse2, Avx, Avx2, Avx512f]));
+        assert_eq!(a & b, set(&[Avx2]));
+        assert_eq!(a - b, set(&[Sse2, Avx]));
+        assert_eq!(b - a, set(&[Avx512f]));
+
+        assert!(set(&[Avx, Avx2]).is_subset(&a));
+        assert!(!b.is_subset(&a));
+        assert!(CpuFeatures::new().is_subset(&b));
+    }
+
+    #[test]
+    fn display_lists_names_in_order() {
+        use CpuFeature::*;
+        assert_eq!(set(&[Avx2, Fpu, Sse2]).to_string(), "fpu sse2 avx2");
+    }
+}
@@ -0,0 +1,357 @@
+use std::{
+    collections::BTreeMap,
+    fs,
+    io::{self, Write},
+    path::Path,
+};
+
+use crate::cpu_info::{
+    CpuData, CpuidDecoder, CpuidResult, CpuidSource, NativeCpuid, ProbeError, cpu_affinity,
+};
+
+const HEADER: &str = "# cpu-info cpuid dump";
+const VERSION: &str = "v2";
+/// Versions `parse` still reads. v1 had no XCR0 lines.
+const READABLE_VERSIONS: [&str; 2] = ["v1", VERSION];
+
+/// Raw CPUID leaves recorded for one or more logical CPUs.
+///
+/// The native text format is one record per line,
+/// `cpu leaf subleaf eax ebx ecx edx` with hexadecimal registers, plus a
+/// `cpu xcr0 value` line per CPU whose OS exposed XCR0. Output of `cpuid -r`
+/// is accepted as well, with an optional `xcr0 = value` line per CPU.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CpuidDump {
+    cpus: BTreeMap<usize, BTreeMap<(u32, u32), CpuidResult>>,
+    xcr0: BTreeMap<usize, u64>,
+}
+
+impl CpuidDump {
+    /// Records every supported leaf on every online logical CPU. CPUs the
+    /// current thread cannot be pinned to are skipped; if none can be pinned
+    /// the leaves of the current CPU are recorded as CPU 0.
+    pub fn capture() -> Self {
+        let mut dump = Self::default();
+        for cpu in cpu_affinity::online_cpus() {
+            if let Some((leaves, xcr0)) =
+                cpu_affinity::run_on_cpu(cpu, || (collect_leaves(&NativeCpuid), NativeCpuid.xcr0()))
+            {
+                dump.record(cpu, leaves, xcr0);
+            }
+        }
+        if dump.cpus.is_empty() {
+            dump.record(0, collect_leaves(&NativeCpuid), NativeCpuid.xcr0());
+        }
+        dump
+    }
+
+    /// Records every supported leaf of a single CPUID source, and its XCR0,
+    /// as CPU 0.
+    pub fn capture_from<S: CpuidSource + ?Sized>(source: &S) -> Self {
+        let mut dump = Self::default();
+        dump.record(0, collect_leaves(source), source.xcr0());
+        dump
+    }
+
+    fn record(&mut self, cpu: usize, leaves: BTreeMap<(u32, u32), CpuidResult>, xcr0: Option<u64>) {
+        self.cpus.insert(cpu, leaves);
+        if let Some(xcr0) = xcr0 {
+            self.xcr0.insert(cpu, xcr0);
+        }
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, ProbeError> {
+        let path = path.as_ref();
+        let text = fs::read_to_string(path).map_err(|e| ProbeError::Io(path.to_path_buf(), e))?;
+        Self::parse(&text)
+    }
+
+    pub fn parse(text: &str) -> Result<Self, ProbeError> {
+        let mut dump = Self::default();
+        let mut current_cpu = 0;
+
+        for (index, line) in text.lines().enumerate() {
+            let line = line.trim();
+            let invalid = |reason: &str| ProbeError::InvalidDump {
+                line: index + 1,
+                reason: reason.to_string(),
+            };
+            if let Some(version) = line.strip_prefix(HEADER) {
+                if !READABLE_VERSIONS.contains(&version.trim()) {
+                    return Err(invalid("unsupported dump version"));
+                }
+                continue;
+            }
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            //cpuid -r: "CPU 3:" followed by "0x00000000 0x00: eax=0x... ebx=... ecx=... edx=...".
+            //cpuid -1 -r prints a bare "CPU:"
+            if let Some(cpu) = line.strip_prefix("CPU").and_then(|r| r.strip_suffix(':')) {
+                current_cpu = match cpu.trim() {
+                    "" => 0,
+                    cpu => cpu.parse().map_err(|_| invalid("invalid CPU number"))?,
+                };
+                continue;
+            }
+            if let Some(value) = line.strip_prefix("xcr0") {
+                let value = value.trim_start().trim_start_matches('=');
+                let xcr0 = parse_hex_u64(value).ok_or_else(|| invalid("invalid XCR0 value"))?;
+                dump.xcr0.insert(current_cpu, xcr0);
+                continue;
+            }
+            if let Some((ids, regs)) = line.split_once(':') {
+                let mut ids = ids.split_whitespace().map(parse_hex);
+                let (Some(Some(leaf)), Some(Some(subleaf))) = (ids.next(), ids.next()) else {
+                    return Err(invalid("invalid leaf or subleaf"));
+                };
+                let mut values = [None; 4];
+                for reg in regs.split_whitespace() {
+                    let Some((name, value)) = reg.split_once('=') else {
+                        return Err(invalid("expected register=value"));
+                    };
+                    let slot = match name {
+                        "eax" => 0,
+                        "ebx" => 1,
+                        "ecx" => 2,
+                        "edx" => 3,
+                        _ => return Err(invalid("unknown register")),
+                    };
+                    values[slot] = parse_hex(value);
+                }
+                let [Some(eax), Some(ebx), Some(ecx), Some(edx)] = values else {
+                    return Err(invalid("missing or invalid register value"));
+                };
+                dump.insert(
+                    current_cpu,
+                    leaf,
+                    subleaf,
+                    CpuidResult::new(eax, ebx, ecx, edx),
+                );
+                continue;
+            }
+
+            //Native: "cpu leaf subleaf eax ebx ecx edx"
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            if let [cpu, "xcr0", value] = fields[..] {
+                let cpu = cpu.parse().map_err(|_| invalid("invalid CPU number"))?;
+                let xcr0 = parse_hex_u64(value).ok_or_else(|| invalid("invalid XCR0 value"))?;
+                dump.xcr0.insert(cpu, xcr0);
+                continue;
+            }
+            if fields.len() != 7 {
+                return Err(invalid("expected 7 fields"));
+            }
+            let cpu = fields[0]
+                .parse()
+                .map_err(|_| invalid("invalid CPU number"))?;
+            let values: Option<Vec<u32>> = fields[1..].iter().map(|f| parse_hex(f)).collect();
+            let Some(values) = values else {
+                return Err(invalid("invalid hexadecimal value"));
+            };
+            dump.insert(
+                cpu,
+                values[0],
+                values[1],
+                CpuidResult::new(values[2], values[3], values[4], values[5]),
+            );
+        }
+
+        if dump.cpus.is_empty() {
+            return Err(ProbeError::InvalidDump {
+                line: 0,
+                reason: "no CPUID records".into(),
+            });
+        }
+        Ok(dump)
+    }
+
+    pub fn write_to(&self, mut writer: impl Write) -> io::Result<()> {
+        writeln!(writer, "{HEADER} {VERSION}")?;
+        writeln!(writer, "# cpu leaf subleaf eax ebx ecx edx")?;
+        for (cpu, leaves) in &self.cpus {
+            if let Some(xcr0) = self.xcr0.get(cpu) {
+                writeln!(writer, "{cpu} xcr0 {xcr0:#018x}")?;
+            }
+            for ((leaf, subleaf), r) in leaves {
+                writeln!(
+                    writer,
+                    "{cpu} {leaf:#010x} {subleaf:#04x} {:#010x} {:#010x} {:#010x} {:#010x}",
+                    r.eax, r.ebx, r.ecx, r.edx
+                )?;
+            }
+        }
+        Ok(())
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), ProbeError> {
+        let path = path.as_ref();
+        let file = fs::File::create(path).map_err(|e| ProbeError::Io(path.to_path_buf(), e))?;
+        self.write_to(io::BufWriter::new(file))
+            .map_err(|e| ProbeError::Io(path.to_path_buf(), e))
+    }
+
+    pub fn insert(&mut self, cpu: usize, leaf: u32, subleaf: u32, result: CpuidResult) {
+        self.cpus
+            .entry(cpu)
+            .or_default()
+            .insert((leaf, subleaf), result);
+    }
+
+    /// Sets the XCR0 replayed for `cpu`.
+    pub fn set_xcr0(&mut self, cpu: usize, xcr0: u64) {
+        self.xcr0.insert(cpu, xcr0);
+    }
+
+    pub fn cpus(&self) -> impl Iterator<Item = usize> + '_ {
+        self.cpus.keys().copied()
+    }
+
+    pub fn cpu(&self, cpu: usize) -> Option<DumpedCpu<'_>> {
+        self.cpus.get(&cpu).map(|leaves| DumpedCpu {
+            leaves,
+            xcr0: self.xcr0.get(&cpu).copied(),
+        })
+    }
+
+    /// Decodes the lowest numbered CPU of the dump.
+    pub fn to_cpu_data(&self) -> Result<CpuData, ProbeError> {
+        let Some(cpu) = self.cpus().next().and_then(|cpu| self.cpu(cpu)) else {
+            return Err(ProbeError::CpuidUnavailable);
+        };
+        CpuData::from_cpuid(&cpu)
+    }
+}
+
+/// Recorded leaves of one logical CPU, replayed as a `CpuidSource`.
+#[derive(Debug, Clone, Copy)]
+pub struct DumpedCpu<'a> {
+    leaves: &'a BTreeMap<(u32, u32), CpuidResult>,
+    xcr0: Option<u64>,
+}
+
+impl CpuidSource for DumpedCpu<'_> {
+    fn cpuid(&self, leaf: u32, subleaf: u32) -> CpuidResult {
+        if let Some(r) = self.leaves.get(&(leaf, subleaf)) {
+            return *r;
+        }
+        //Leaves without subleaves ignore ECX, so they are recorded once.
+        match self.leaves.get(&(leaf, 0)) {
+            Some(r) if !has_subleaves(leaf) => *r,
+            _ => CpuidResult::default(),
+        }
+    }
+
+    fn xcr0(&self) -> Option<u64> {
+        self.xcr0
+    }
+}
+
+fn parse_hex(value: &str) -> Option<u32> {
+    u32::try_from(parse_hex_u64(value)?).ok()
+}
+
+fn parse_hex_u64(value: &str) -> Option<u64> {
+    let value = value.trim();
+    let digits = value
+        .strip_prefix("0x")
+        .or_else(|| value.strip_prefix("0X"))
+        .unwrap_or(value);
+    u64::from_str_radix(digits, 16).ok()
+}
+
+fn collect_leaves<S: CpuidSource + ?Sized>(source: &S) -> BTreeMap<(u32, u32), CpuidResult> {
+    let mut leaves = BTreeMap::new();
+
+    let max_basic = source.cpuid(0, 0).eax.min(0xFF);
+    for leaf in 0..=max_basic {
+        collect_leaf(source, leaf, &mut leaves);
+    }
+
+    for (base, max) in CpuidDecoder::new(source).hypervisor_bases() {
+        for leaf in base..=max {
+            collect_leaf(source, leaf, &mut leaves);
+        }
+    }
+
+    let max_extended = source.cpuid(0x8000_0000, 0).eax;
+    if (0x8000_0000..=0x8000_00FF).contains(&max_extended) {
+        for leaf in 0x8000_0000..=max_extended {
+            collect_leaf(source, leaf, &mut leaves);
+        }
+    }
+
+    leaves
+}
+
+fn has_subleaves(leaf: u32) -> bool {
+    matches!(
+        leaf,
+        0x4 | 0x7
+            | 0xB
+            | 0xD
+            | 0xF
+            | 0x10
+            | 0x12
+            | 0x14
+            | 0x17
+            | 0x18
+            | 0x1D
+            | 0x1F
+            | 0x20
+            | 0x23
+            | 0x8000_001D
+            | 0x8000_0020
+            | 0x8000_0026
+    )
+}
+
+fn collect_leaf<S: CpuidSource + ?Sized>(
+    source: &S,
+    leaf: u32,
+    leaves: &mut BTreeMap<(u32, u32), CpuidResult>,
+) {
+    let first = source.cpuid(leaf, 0);
+    leaves.insert((leaf, 0), first);
+
+    let last_subleaf = match leaf {
+        //Cache parameters, terminated by a null cache type
+        0x4 | 0x8000_001D => {
+            let mut sub = 0;
+            while sub < 32 && source.cpuid(leaf, sub).eax & 0x1F != 0 {
+                sub += 1;
+            }
+            sub
+        }
+        //Extended topology, terminated by an invalid level type
+        0xB | 0x1F | 0x8000_0026 => {
+            let mut sub = 0;
+            while sub < 8 && (source.cpuid(leaf, sub).ecx >> 8) & 0xFF != 0 {
+                sub += 1;
+            }
+            sub
+        }
+        //Subleaf 0 EAX holds the highest subleaf
+        0x7 | 0x14 | 0x17 | 0x18 | 0x1D | 0x20 | 0x23 => first.eax.min(31),
+        0xD => 63,
+        0xF | 0x10 | 0x8000_0020 => 3,
+        //SGX EPC sections, terminated by an invalid subleaf type
+        0x12 => {
+            let mut sub = 2;
+            while sub < 16 && source.cpuid(leaf, sub).eax & 0xF != 0 {
+                sub += 1;
+            }
+            sub
+        }
+        _ => 0,
+    };
+
+    for sub in 1..=last_subleaf {
+        let r = source.cpuid(leaf, sub);
+        if leaf == 0xD && r.is_zero() {
+            continue;
+        }
+        leaves.insert((leaf, sub), r);
+    }
+}
@@ -0,0 +1,86 @@
+use std::fmt;
+
+use crate::cpu_info::{CpuData, CpuFeature, CpuField};
+
+//XCR0 state components
+const SSE_STATE: u64 = 1 << 1;
+const AVX_STATE: u64 = 1 << 2;
+const MPX_STATE: u64 = (1 << 3) | (1 << 4);
+const AVX512_STATE: u64 = (1 << 5) | (1 << 6) | (1 << 7);
+const PKRU_STATE: u64 = 1 << 9;
+const AMX_STATE: u64 = (1 << 17) | (1 << 18);
+const APX_STATE: u64 = 1 << 19;
+
+/// How far a feature is available, from the CPU up to the current process.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum FeatureSupport {
+    Unsupported,
+    /// Reported by CPUID, but the OS has not enabled its register state.
+    SupportedByCpu,
+    /// Reported by CPUID, but whether the OS enabled its register state is
+    /// unknown because XCR0 could not be read.
+    Unknown,
+    /// Register state enabled in XCR0, but this process lacks permission to
+    /// use it (Linux AMX without `ARCH_REQ_XCOMP_PERM`).
+    EnabledByOs,
+    Usable,
+}
+
+impl fmt::Display for FeatureSupport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            FeatureSupport::Unsupported => "unsupported",
+            FeatureSupport::SupportedByCpu => "supported by CPU",
+            FeatureSupport::Unknown => "supported by CPU, OS state unknown",
+            FeatureSupport::EnabledByOs => "enabled by OS",
+            FeatureSupport::Usable => "usable",
+        };
+        write!(f, "{name}")
+    }
+}
+
+impl CpuFeature {
+    /// XCR0 state components the OS must enable before the feature can be
+    /// used. Zero for features without extended register state.
+    pub fn xsave_state(self) -> u64 {
+        use CpuFeature::*;
+        match self {
+            Avx | Avx2 | Fma | F16c | Vaes | Vpclmulqdq | AvxVnni | AvxIfma | AvxVnniInt8
+            | AvxNeConvert | AvxVnniInt16 | Sha512 | Sm3 | Sm4 => SSE_STATE | AVX_STATE,
+            Avx512f | Avx512dq | Avx512ifma | Avx512pf | Avx512er | Avx512cd | Avx512bw
+            | Avx512vl | Avx512vbmi | Avx512Vbmi2 | Avx512Vnni | Avx512Bitalg | Avx512Vpopcntdq
+            | Avx512_4vnniw | Avx512_4fmaps | Avx512Vp2intersect | Avx512Fp16 | Avx512Bf16
+            | Avx10 => SSE_STATE | AVX_STATE | AVX512_STATE,
+            AmxTile | AmxInt8 | AmxBf16 | AmxFp16 | AmxComplex => AMX_STATE,
+            ApxF => APX_STATE,
+            Mpx => MPX_STATE,
+            Pku => PKRU_STATE,
+            _ => 0,
+        }
+    }
+}
+
+impl CpuData {
+    pub fn support(&self, feature: CpuFeature) -> FeatureSupport {
+        let state = feature.xsave_state();
+        if !self.has(feature) {
+            FeatureSupport::Unsupported
+        } else if state != 0 && !self.is_known(CpuField::XsaveState) {
+            FeatureSupport::Unknown
+        } else if self.usable_state & state == state {
+            FeatureSupport::Usable
+        } else if self.enabled_state & state == state {
+            FeatureSupport::EnabledByOs
+        } else {
+            FeatureSupport::SupportedByCpu
+        }
+    }
+
+    /// Whether instructions of `feature` can be executed by this process
+    /// without faulting.
+    pub fn is_usable(&self, feature: CpuFeature) -> bool {
+        self.support(feature) == FeatureSupport::Usable
+    }
+}
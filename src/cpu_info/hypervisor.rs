@@ -0,0 +1,246 @@
+use std::fmt;
+
+use crate::cpu_info::{CpuData, CpuFeature, CpuidDecoder, CpuidSource};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum HypervisorVendor {
+    Kvm,
+    HyperV,
+    VMware,
+    Xen,
+    /// QEMU without hardware acceleration.
+    QemuTcg,
+    VirtualBox,
+    Parallels,
+    Bhyve,
+    Acrn,
+    Unknown,
+}
+
+impl fmt::Display for HypervisorVendor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            HypervisorVendor::Kvm => "KVM",
+            HypervisorVendor::HyperV => "Hyper-V",
+            HypervisorVendor::VMware => "VMware",
+            HypervisorVendor::Xen => "Xen",
+            HypervisorVendor::QemuTcg => "QEMU TCG",
+            HypervisorVendor::VirtualBox => "VirtualBox",
+            HypervisorVendor::Parallels => "Parallels",
+            HypervisorVendor::Bhyve => "bhyve",
+            HypervisorVendor::Acrn => "ACRN",
+            HypervisorVendor::Unknown => "unknown hypervisor",
+        };
+        write!(f, "{name}")
+    }
+}
+
+/// Hypervisor the CPU data was probed under, from the CPUID leaves it
+/// reserves at 0x40000000 or, when it emulates another hypervisor there, at
+/// a higher multiple of 0x100.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Hypervisor {
+    pub(crate) vendor: HypervisorVendor,
+    pub(crate) signature: String,
+    pub(crate) max_leaf: u32,
+    pub(crate) version: Option<String>,
+    pub(crate) features: Vec<String>,
+}
+
+impl Hypervisor {
+    pub fn vendor(&self) -> HypervisorVendor {
+        self.vendor
+    }
+
+    /// Raw vendor signature of leaf 0x40000000, e.g. "KVMKVMKVM".
+    pub fn signature(&self) -> &str {
+        &self.signature
+    }
+
+    /// Highest hypervisor leaf advertised.
+    pub fn max_leaf(&self) -> u32 {
+        self.max_leaf
+    }
+
+    pub fn version(&self) -> Option<&str> {
+        self.version.as_deref()
+    }
+
+    /// Paravirtual features the hypervisor advertises: KVM features, Hyper-V
+    /// privileges and enlightenment recommendations, or Xen interface
+    /// features. Empty for other hypervisors.
+    pub fn features(&self) -> &[String] {
+        &self.features
+    }
+}
+
+const SIGNATURES: [(&str, HypervisorVendor); 10] = [
+    ("KVMKVMKVM", HypervisorVendor::Kvm),
+    ("Linux KVM Hv", HypervisorVendor::Kvm),
+    ("Microsoft Hv", HypervisorVendor::HyperV),
+    ("VMwareVMware", HypervisorVendor::VMware),
+    ("XenVMMXenVMM", HypervisorVendor::Xen),
+    ("TCGTCGTCGTCG", HypervisorVendor::QemuTcg),
+    ("VBoxVBoxVBox", HypervisorVendor::VirtualBox),
+    ("lrpepyh  vr", HypervisorVendor::Parallels),
+    ("bhyve bhyve", HypervisorVendor::Bhyve),
+    ("ACRNACRNACRN", HypervisorVendor::Acrn),
+];
+
+//Leaf 0x40000001 EAX
+const KVM_FEATURES: &[(u32, &str)] = &[
+    (0, "clocksource"),
+    (1, "nop_io_delay"),
+    (2, "mmu_op"),
+    (3, "clocksource2"),
+    (4, "async_pf"),
+    (5, "steal_time"),
+    (6, "pv_eoi"),
+    (7, "pv_unhalt"),
+    (9, "pv_tlb_flush"),
+    (10, "async_pf_vmexit"),
+    (11, "pv_send_ipi"),
+    (12, "poll_control"),
+    (13, "pv_sched_yield"),
+    (14, "async_pf_int"),
+    (15, "msi_ext_dest_id"),
+    (16, "hc_map_gpa_range"),
+    (17, "migration_control"),
+    (24, "clocksource_stable_bit"),
+];
+
+//Leaf 0x40000003 EAX, partition privileges
+const HYPERV_PRIVILEGES: &[(u32, &str)] = &[
+    (0, "vp_runtime"),
+    (1, "time_ref_count"),
+    (2, "synic"),
+    (3, "stimer"),
+    (4, "apic_access"),
+    (5, "hypercall"),
+    (6, "vp_index"),
+    (7, "reset"),
+    (8, "stats"),
+    (9, "reference_tsc"),
+    (10, "guest_idle"),
+    (11, "frequency_msrs"),
+    (12, "debug_msrs"),
+    (13, "reenlightenment"),
+];
+
+//Leaf 0x40000004 EAX, enlightenment recommendations
+const HYPERV_RECOMMENDATIONS: &[(u32, &str)] = &[
+    (0, "hint_as_switch"),
+    (1, "hint_local_tlb_flush"),
+    (2, "hint_remote_tlb_flush"),
+    (3, "hint_apic_msrs"),
+    (4, "hint_reset"),
+    (5, "hint_relaxed_timing"),
+    (6, "hint_dma_remapping"),
+    (7, "hint_interrupt_remapping"),
+    (8, "hint_x2apic_msrs"),
+    (9, "hint_deprecate_auto_eoi"),
+    (10, "hint_cluster_ipi"),
+    (11, "hint_ex_processor_masks"),
+    (12, "hint_nested"),
+    (13, "hint_int_mbec"),
+    (14, "hint_enlightened_vmcs"),
+];
+
+//Leaf 0x40000004 EAX on Xen
+const XEN_FEATURES: &[(u32, &str)] = &[
+    (0, "virt_apic"),
+    (1, "virt_x2apic"),
+    (3, "vcpu_id_present"),
+    (4, "domid_present"),
+];
+
+fn set_bits(value: u32, names: &[(u32, &str)]) -> impl Iterator<Item = String> {
+    names
+        .iter()
+        .filter(move |(bit, _)| value & (1 << bit) != 0)
+        .map(|(_, name)| name.to_string())
+}
+
+impl<S: CpuidSource + ?Sized> CpuidDecoder<'_, S> {
+    /// Hypervisor reported through leaf 0x40000000, `None` on bare metal.
+    /// The interface with the highest base and a known signature wins, the
+    /// ones below it are emulated.
+    pub fn hypervisor(&self) -> Option<Hypervisor> {
+        let interfaces: Vec<(u32, u32, String, HypervisorVendor)> = self
+            .hypervisor_bases()
+            .into_iter()
+            .map(|(base, max_leaf)| {
+                let signature = self.hypervisor_signature(base);
+                let vendor = SIGNATURES
+                    .iter()
+                    .find(|(s, _)| *s == signature)
+                    .map_or(HypervisorVendor::Unknown, |&(_, vendor)| vendor);
+                (base, max_leaf, signature, vendor)
+            })
+            .collect();
+        let (base, max_leaf, signature, vendor) = interfaces
+            .iter()
+            .rev()
+            .find(|(.., vendor)| *vendor != HypervisorVendor::Unknown)
+            .or(interfaces.first())
+            .cloned()?;
+
+        let leaf = |offset: u32| self.leaf(base + offset, 0).unwrap_or_default();
+        let (version, features) = match vendor {
+            HypervisorVendor::Kvm => {
+                let r = leaf(1);
+                let mut features: Vec<String> = set_bits(r.eax, KVM_FEATURES).collect();
+                if r.edx & 1 != 0 {
+                    features.push("hint_realtime".into());
+                }
+                (None, features)
+            }
+            HypervisorVendor::HyperV => {
+                let build = leaf(2);
+                let version = (build.ebx != 0)
+                    .then(|| format!("{}.{}.{}", build.ebx >> 16, build.ebx & 0xFFFF, build.eax));
+                let features = set_bits(leaf(3).eax, HYPERV_PRIVILEGES)
+                    .chain(set_bits(leaf(4).eax, HYPERV_RECOMMENDATIONS))
+                    .collect();
+                (version, features)
+            }
+            HypervisorVendor::Xen => {
+                let version = leaf(1).eax;
+                let version =
+                    (version != 0).then(|| format!("{}.{}", version >> 16, version & 0xFFFF));
+                (version, set_bits(leaf(4).eax, XEN_FEATURES).collect())
+            }
+            _ => (None, Vec::new()),
+        };
+
+        Some(Hypervisor {
+            vendor,
+            signature,
+            max_leaf,
+            version,
+            features,
+        })
+    }
+
+    fn hypervisor_signature(&self, base: u32) -> String {
+        let r = self.leaf(base, 0).unwrap_or_default();
+        let mut signature = Vec::new();
+        for reg in [r.ebx, r.ecx, r.edx] {
+            signature.extend_from_slice(&reg.to_le_bytes());
+        }
+        String::from_utf8_lossy(&signature)
+            .trim_matches(|c: char| c == '\0' || c == ' ')
+            .to_string()
+    }
+}
+
+impl CpuData {
+    /// Whether the CPU is virtualized, so topology, caches and clocks
+    /// describe the guest rather than the host.
+    pub fn is_virtualized(&self) -> bool {
+        self.hypervisor.is_some() || self.has(CpuFeature::Hypervisor)
+    }
+}
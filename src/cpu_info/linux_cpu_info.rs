@@ -1,252 +1,747 @@
-use crate::cpu_info::{CpuData, CpuInfo};
-use std::fs;
+use crate::cpu_info::{
+    AddressWidths, CacheDescriptor, CacheType, Confidence, CoreGroup, CoreType, CpuData,
+    CpuFeature, CpuField, CpuInfo, ProbeError, ProbeOptions, Provenance, Source, TopologyLevel,
+    TopologyNode,
+    cache::cpuid_caches,
+    core_types::cpuid_core_groups,
+    cpu_affinity::parse_cpu_list,
+    logical_cpus::cpuid_logical_cpus,
+    topology::{TopologyIds, cpuid_topology, topology_tree},
+    tsc::calibrate_tsc,
+    x86_cpuid::decode_cpu_data,
+};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fs,
+    path::{Path, PathBuf},
+};
+
+pub struct LinuxCpuInfo {
+    data: CpuData,
+    root: PathBuf,
+}
 
-pub struct LinuxCpuInfo(CpuData);
 impl CpuInfo for LinuxCpuInfo {
-    fn new() -> Self {
-        let mut results = CpuData::default();
-        results.arch = std::env::consts::ARCH.to_string();
-
-        let (_, b, c, d) = cpuid(0, 0);
-        let mut vendor = Vec::new();
-        vendor.extend_from_slice(&b.to_le_bytes());
-        vendor.extend_from_slice(&d.to_le_bytes());
-        vendor.extend_from_slice(&c.to_le_bytes());
-        results.vendor = String::from_utf8_lossy(&vendor).trim().to_string();
-
-        let max_ext = cpuid(0x80000000, 0).0;
-        if max_ext >= 0x80000004 {
-            for leaf in 0x80000002..=0x80000004 {
-                let (ea, eb, ec, ed) = cpuid(leaf, 0);
-                for r in [ea, eb, ec, ed] {
-                    results
-                        .brand
-                        .push_str(&String::from_utf8_lossy(&r.to_le_bytes()));
+    fn with_options(options: &ProbeOptions) -> Result<Self, ProbeError> {
+        let root = options.root().to_path_buf();
+
+        //Without CPUID everything comes from /proc/cpuinfo and sysfs
+        let cpuid_data = match options.cpuid_source() {
+            Some(source) => decode_cpu_data(&*source, options),
+            None => Err(ProbeError::CpuidUnavailable),
+        };
+        let needs_cpuinfo = match &cpuid_data {
+            Ok(results) => {
+                options.wants(CpuField::Microcode)
+                    || options.wants(CpuField::Topology)
+                    || options.wants(CpuField::LogicalCpus)
+                    || (options.wants(CpuField::ClockSpeed) && results.clock_speed == 0)
+            }
+            Err(_) => true,
+        };
+        let cpuinfo_path = root.join("proc/cpuinfo");
+        let cpuinfo = if needs_cpuinfo {
+            fs::read_to_string(&cpuinfo_path).map_err(|e| ProbeError::Io(cpuinfo_path, e))
+        } else {
+            Ok(String::new())
+        };
+        let (mut results, cpuinfo) = match (cpuid_data, cpuinfo) {
+            (Ok(results), cpuinfo) => (results, cpuinfo.ok()),
+            (Err(ProbeError::CpuidUnavailable), Ok(cpuinfo)) => {
+                (decode_proc_cpuinfo(&root, &cpuinfo, options), Some(cpuinfo))
+            }
+            (Err(ProbeError::CpuidUnavailable), Err(e)) | (Err(e), _) => return Err(e),
+        };
+
+        //A replayed dump only matches /proc and /sys of a captured tree
+        let same_cpu = options.probes_host() || root != Path::new("/");
+
+        //Caches, sysfs knows which CPUs share each instance. Without it CPUID
+        //runs on every CPU
+        if options.wants(CpuField::Caches) {
+            let caches = if same_cpu {
+                read_sysfs_caches(&root, &sysfs_cpus(&root))
+            } else {
+                Vec::new()
+            };
+            if !caches.is_empty() {
+                results.set_os_caches(caches, Source::Sysfs("cache/index*".into()));
+            } else if results.provenance(CpuField::Caches).is_none() {
+                results.warn(CpuField::Caches, "no sysfs cache entries");
+            } else {
+                results.set_cpuid_caches(cpuid_caches(options));
+            }
+        }
+
+        //Topology, decoded from every CPU's APIC ID and checked against sysfs
+        if options.wants(CpuField::Topology) {
+            let sysfs = read_sysfs_topology_ids(&root, &sysfs_cpus(&root))
+                .filter(|ids| same_cpu && !ids.is_empty())
+                .map(|ids| topology_tree(&ids));
+            let apic_ids = if same_cpu {
+                cpuinfo.as_deref().and_then(proc_apic_ids)
+            } else {
+                None
+            };
+            let sysfs_source = Source::Sysfs("topology".into());
+            match (cpuid_topology(options, apic_ids), sysfs) {
+                (Some((tree, _)), Some(sysfs)) if !same_shape(&tree, &sysfs) => {
+                    results.set_topology(sysfs, sysfs_source, Confidence::Medium);
+                }
+                (Some((tree, leaf)), _) => {
+                    results.set_topology(tree, Source::Cpuid { leaf }, Confidence::High);
+                }
+                (None, Some(sysfs)) => {
+                    results.set_topology(sysfs, sysfs_source, Confidence::High);
+                }
+                (None, None) => {}
+            }
+        }
+
+        //Hybrid core types, the sysfs PMUs and group clocks belong to this machine
+        if options.wants(CpuField::CoreTypes) {
+            let sysfs = if same_cpu {
+                read_sysfs_core_groups(&root)
+            } else {
+                None
+            };
+            let (mut groups, source) = match sysfs {
+                Some(groups) => (groups, Source::Sysfs("cpu_core/cpus".into())),
+                None => {
+                    let (groups, caches) = cpuid_core_groups(options);
+                    if !groups.is_empty() {
+                        results.set_cpuid_caches(caches);
+                    }
+                    (groups, Source::Cpuid { leaf: 0x1A })
+                }
+            };
+            if !groups.is_empty() {
+                if same_cpu {
+                    for group in &mut groups {
+                        read_group_clocks(&root, group);
+                    }
+                }
+                results.set_core_groups(groups);
+                results.record(CpuField::CoreTypes, source, Confidence::High);
+            } else if results.has(CpuFeature::HybridCpu) && !same_cpu {
+                results.warn(
+                    CpuField::CoreTypes,
+                    "sysfs core types not available for a replayed dump",
+                );
+            } else if results.has(CpuFeature::HybridCpu) {
+                results.warn(
+                    CpuField::CoreTypes,
+                    "no sysfs or CPUID leaf 0x1A core types",
+                );
+            }
+        }
+
+        //Per-CPU table, /proc/cpuinfo has each CPU's microcode unless a dump is replayed
+        if options.wants(CpuField::LogicalCpus) {
+            let mut cpus = cpuid_logical_cpus(options);
+            let microcode = cpuinfo
+                .as_deref()
+                .filter(|_| same_cpu)
+                .map(per_cpu_microcode)
+                .unwrap_or_default();
+            for cpu in &mut cpus {
+                if let Some(revision) = microcode.get(&cpu.cpu) {
+                    cpu.microcode = revision.clone();
+                }
+            }
+            results.set_logical_cpus(cpus);
+        }
+
+        //Dynamically enabled state such as AMX tiles needs a per-process
+        //permission, which only means something for this machine
+        if options.probes_host()
+            && root == Path::new("/")
+            && let Some(permitted) = xcomp_permissions()
+        {
+            results.usable_state &= permitted;
+        }
+
+        if options.wants(CpuField::ClockSpeed) && results.clock_speed == 0 {
+            let base_mhz = if same_cpu {
+                read_base_mhz(&root, cpuinfo.as_deref())
+            } else {
+                None
+            };
+            match base_mhz {
+                Some((base_clock, provenance)) => {
+                    results.clock_speed = base_clock;
+                    results.resolve(CpuField::ClockSpeed, provenance);
                 }
+                None if !same_cpu => results.warn(
+                    CpuField::ClockSpeed,
+                    "CPUID leaf 0x16 unsupported and sysfs not available for a replayed dump",
+                ),
+                None => results.warn(
+                    CpuField::ClockSpeed,
+                    "CPUID leaf 0x16 unsupported and no sysfs or /proc/cpuinfo frequency",
+                ),
             }
-            results.brand = results.brand.trim_matches(char::from(0)).trim().to_string();
-        }
-
-        let (eax, _, ecx, edx) = cpuid(1, 0);
-
-        results.stepping = (eax & 0xF) as u8;
-        let model = ((eax >> 4) & 0xF) as u8;
-        let family = ((eax >> 8) & 0xF) as u8;
-        let ext_model = ((eax >> 16) & 0xF) as u8;
-        let ext_family = ((eax >> 20) & 0xFF) as u8;
-        results.family = if family == 0xF { family + ext_family } else { family };
-        results.model = if family == 0x6 || family == 0xF { (ext_model << 4) + model } else { model };
-
-        if (ecx & (1 << 23)) != 0 { results.flags.push("POP_CNT".into()); }
-        if (edx & (1 << 25)) != 0 { results.flags.push("SSE".into()); }
-        if (edx & (1 << 26)) != 0 { results.flags.push("SSE2".into()); }
-        if (ecx & (1 << 0)) != 0 { results.flags.push("SSE3".into()); }
-        if (ecx & (1 << 9)) != 0 { results.flags.push("SSSE3".into()); }
-        if (ecx & (1 << 19)) != 0 { results.flags.push("SSE4.1".into()); }
-        if (ecx & (1 << 20)) != 0 { results.flags.push("SSE4.2".into()); }
-        if (ecx & (1 << 25)) != 0 { results.flags.push("AES".into()); }
-        if (ecx & (1 << 28)) != 0 { results.flags.push("AVX".into()); }
-
-        let (_, ebx, _, _) = cpuid(7, 0);
-        if (ebx & (1 << 5)) != 0 { results.flags.push("AVX2".into()); }
-        if (ebx & (1 << 8)) != 0 { results.flags.push("BMI2".into()); }
-        if (ebx & (1 << 16)) != 0 { results.flags.push("AVX512F".into()); }
-        if (ebx & (1 << 30)) != 0 { results.flags.push("AVX512BW".into()); }
-        if (ebx & (1 << 31)) != 0 { results.flags.push("AVX512V1".into()); }
-
-        (
-            results.cores,
-            results.threads,
-            results.l1_cache,
-            results.l2_cache,
-            results.l3_cache,
-        ) = topo_and_caches();
-
-        (results.clock_speed, results.clock_speed_turbo, _, _) = cpuid(0x16, 0);
-        if results.clock_speed == 0 {
-            if let Some(base_clock) = read_base_mhz() {
-                results.clock_speed = base_clock
+        }
+
+        //TSC, calibrated only on the host when CPUID has no ratio
+        if options.wants(CpuField::TscFrequency) && results.tsc_frequency == 0 {
+            let host = options.calibrates_tsc() && root == Path::new("/");
+            match host
+                .then(|| calibrate_tsc(("CLOCK_MONOTONIC_RAW", monotonic_raw_ns)))
+                .flatten()
+            {
+                Some((hz, source)) => {
+                    results.tsc_frequency = hz;
+                    let confidence = tsc_clocksource_confidence(&root);
+                    results.record(CpuField::TscFrequency, source, confidence);
+                }
+                None if !options.calibrates_tsc() => results.warn(
+                    CpuField::TscFrequency,
+                    "no CPUID TSC ratio, and calibration not requested",
+                ),
+                None => results.warn(CpuField::TscFrequency, "no CPUID TSC ratio"),
             }
         }
 
-        results.microcode = read_microcode().unwrap_or_default();
+        if options.wants(CpuField::Microcode) && !same_cpu {
+            results.warn(
+                CpuField::Microcode,
+                "/proc/cpuinfo not available for a replayed dump",
+            );
+        } else if options.wants(CpuField::Microcode) {
+            match read_microcode(cpuinfo.as_deref()) {
+                Ok(microcode) => {
+                    results.microcode = microcode;
+                    results.record(
+                        CpuField::Microcode,
+                        Source::ProcCpuinfo("microcode".into()),
+                        Confidence::High,
+                    );
+                }
+                Err(reason) => results.warn(CpuField::Microcode, reason),
+            }
+        }
+        results.skip_unrequested(options);
 
-        Self(results)
+        Ok(Self {
+            data: results,
+            root,
+        })
     }
 
     fn static_data(&self) -> &CpuData {
-        &self.0
+        &self.data
     }
 
     fn uptime(&self) -> f64 {
-        if let Ok(s) = fs::read_to_string("/proc/uptime") {
-            if let Some(first) = s.split_whitespace().next() {
-                if let Ok(v) = first.parse::<f64>() {
-                    return v;
-                }
-            }
+        if let Ok(s) = fs::read_to_string(self.root.join("proc/uptime"))
+            && let Some(first) = s.split_whitespace().next()
+            && let Ok(v) = first.parse::<f64>()
+        {
+            return v;
         }
         0.0
     }
 }
 
-#[inline]
-fn cpuid(eax: u32, ecx: u32) -> (u32, u32, u32, u32) {
-    if cfg!(target_arch = "x86_64") {
-        unsafe {
-            let r = core::arch::x86_64::__cpuid_count(eax, ecx);
-            (r.eax, r.ebx, r.ecx, r.edx)
-        }
-    } else {
-        (0, 0, 0, 0)
-    }
+/// XSAVE state components this process may use, from
+/// `ARCH_GET_XCOMP_PERM`. `None` on kernels without dynamic state.
+#[cfg(target_arch = "x86_64")]
+fn xcomp_permissions() -> Option<u64> {
+    const ARCH_GET_XCOMP_PERM: libc::c_long = 0x1022;
+    let mut permitted = 0u64;
+    let result = unsafe {
+        libc::syscall(
+            libc::SYS_arch_prctl,
+            ARCH_GET_XCOMP_PERM,
+            &mut permitted as *mut u64,
+        )
+    };
+    (result == 0).then_some(permitted)
 }
 
-fn topo_and_caches() -> (usize, usize, usize, usize, usize) {
-    let mut logical = 0u32;
-    let mut smt = 0u32;
-
-    let mut try_leaf = |leaf: u32| -> bool {
-        let max_basic = cpuid(0, 0).0;
-        if max_basic < leaf { return false; }
-        let mut sub = 0u32;
-        loop {
-            let (ea, eb, ec, _) = cpuid(leaf, sub);
-            let level_type = (ec >> 8) & 0xff;
-            let level_count = eb & 0xffff;
-            if level_count == 0 { break; }
-            if level_type == 1 { smt = level_count; }
-            if level_type == 2 { logical = level_count; }
-            if (ea & 0x1f) == 0 { break; }
-            sub += 1;
-        }
-        logical != 0
-    };
+#[cfg(not(target_arch = "x86_64"))]
+fn xcomp_permissions() -> Option<u64> {
+    None
+}
 
-    let ok = try_leaf(0x1f) || try_leaf(0x0b);
+/// Builds `CpuData` from `/proc/cpuinfo` and sysfs alone, used when CPUID is
+/// disabled or the CPU is not x86.
+fn decode_proc_cpuinfo(root: &Path, cpuinfo: &str, options: &ProbeOptions) -> CpuData {
+    let mut results = CpuData::default();
+    let value = |key: &str| cpuinfo_value(cpuinfo, key);
+    let source = |key: &str| Source::ProcCpuinfo(key.into());
 
-    let (cores, threads) = if ok {
-        let smt_eff = smt.max(1);
-        let phys = (logical / smt_eff).max(1);
-        (phys as usize, logical as usize)
+    //Vendor
+    if let Some(vendor) = value("vendor_id") {
+        results.vendor = vendor.to_string();
+        results.record(CpuField::Vendor, source("vendor_id"), Confidence::High);
+    } else if let Some(implementer) = value("CPU implementer").and_then(parse_number) {
+        results.vendor = arm_implementer(implementer).to_string();
+        results.record(
+            CpuField::Vendor,
+            source("CPU implementer"),
+            Confidence::High,
+        );
     } else {
-        let vendor = {
-            let (_, b, c, d) = cpuid(0, 0);
-            let mut v = Vec::new();
-            v.extend_from_slice(&b.to_le_bytes());
-            v.extend_from_slice(&d.to_le_bytes());
-            v.extend_from_slice(&c.to_le_bytes());
-            String::from_utf8_lossy(&v).to_string()
+        results.warn(CpuField::Vendor, "no vendor_id in /proc/cpuinfo");
+    }
+
+    //Brand
+    match value("model name") {
+        Some(brand) => {
+            results.brand = brand.to_string();
+            results.record(CpuField::Brand, source("model name"), Confidence::High);
+        }
+        None => results.warn(CpuField::Brand, "no model name in /proc/cpuinfo"),
+    }
+
+    //Family/Model/Stepping
+    for (field, keys) in [
+        (CpuField::Family, ["cpu family", "CPU architecture"]),
+        (CpuField::Model, ["model", "CPU part"]),
+        (CpuField::Stepping, ["stepping", "CPU revision"]),
+    ] {
+        let Some(key) = keys.into_iter().find(|&key| value(key).is_some()) else {
+            results.warn(field, format!("no {} in /proc/cpuinfo", keys[0]));
+            continue;
         };
-        if cpuid(0x8000_0000, 0).0 >= 0x8000_0008 && vendor.contains("AuthenticAMD") {
-            let (_, eb, _, _) = cpuid(1, 0);
-            let lpp = ((eb >> 16) & 0xff) as u32;
-            let phys = ((cpuid(0x8000_0008, 0).2 & 0xff) + 1) as u32;
-            let l = if lpp != 0 { lpp } else { phys };
-            (phys as usize, l as usize)
-        } else if cpuid(0, 0).0 >= 4 && vendor.contains("GenuineIntel") {
-            let mut i = 0u32;
-            let mut max_phys_minus1 = 0u32;
-            loop {
-                let (eax, _, _, _) = cpuid(4, i);
-                if (eax & 0x1f) == 0 { break; }
-                let c = (eax >> 26) & 0x3f;
-                if c > max_phys_minus1 { max_phys_minus1 = c; }
-                i += 1;
+        match value(key).and_then(parse_number).map(u8::try_from) {
+            Some(Ok(number)) => {
+                match field {
+                    CpuField::Family => results.family = number,
+                    CpuField::Model => results.model = number,
+                    _ => results.stepping = number,
+                }
+                results.record(field, source(key), Confidence::High);
             }
-            let phys = (max_phys_minus1 + 1).max(1);
-            let lpp = ((cpuid(1, 0).1 >> 16) & 0xff) as u32;
-            let l = if lpp != 0 { lpp } else { phys };
-            (phys as usize, l as usize)
+            _ => results.warn(
+                field,
+                format!("{key} in /proc/cpuinfo is not an 8-bit number"),
+            ),
+        }
+    }
+
+    //Flags
+    match ["flags", "Features"]
+        .into_iter()
+        .find(|&key| value(key).is_some())
+    {
+        Some(key) => {
+            results.flags = value(key)
+                .unwrap_or_default()
+                .split_whitespace()
+                .filter_map(CpuFeature::from_name)
+                .collect();
+            //The kernel hides osxsave, it clears xsave when not using XSAVE
+            let osxsave = results.has(CpuFeature::Xsave);
+            results.flags.set(CpuFeature::Osxsave, osxsave);
+            results.record(CpuField::Flags, source(key), Confidence::High);
+        }
+        None => results.warn(CpuField::Flags, "no flags in /proc/cpuinfo"),
+    }
+
+    //XSAVE state, the kernel hides the flags of features whose state it left
+    //disabled in XCR0. x87 and SSE state are always enabled with XSAVE
+    if options.wants(CpuField::XsaveState) {
+        if value("flags").is_some() {
+            let base = if results.has(CpuFeature::Xsave) {
+                0b11
+            } else {
+                0
+            };
+            let state = results
+                .flags
+                .iter()
+                .fold(base, |state, feature| state | feature.xsave_state());
+            results.enabled_state = state;
+            results.usable_state = state;
+            results.record(CpuField::XsaveState, source("flags"), Confidence::Medium);
         } else {
-            let lpp = ((cpuid(1, 0).1 >> 16) & 0xff) as usize;
-            let l = if lpp != 0 { lpp } else { 1 };
-            (1, l)
+            results.warn(CpuField::XsaveState, "XCR0 is only reported by XGETBV");
         }
-    };
+    }
 
-    let (l1, l2, l3) = cache_size(cores, threads);
-    (cores, threads, l1, l2, l3)
-}
+    //Hypervisor, only its presence shows up in the flags
+    if results.has(CpuFeature::Hypervisor) {
+        results.warn(
+            CpuField::Hypervisor,
+            "hypervisor leaves are only reported by CPUID",
+        );
+    } else if value("flags").is_some() {
+        results.record(CpuField::Hypervisor, source("flags"), Confidence::Medium);
+    }
 
-fn read_base_mhz() -> Option<u32> {
-    if let Ok(s) = fs::read_to_string("/sys/devices/system/cpu/cpu0/cpufreq/base_frequency") {
-        if let Ok(khz) = s.trim().parse::<u32>() {
-            return Some(khz / 1000);
+    //Address widths, "46 bits physical, 48 bits virtual"
+    let bits = |kind: &str| {
+        value("address sizes")?
+            .split(',')
+            .find(|part| part.trim().ends_with(kind))?
+            .split_whitespace()
+            .next()?
+            .parse::<u8>()
+            .ok()
+    };
+    match (bits("physical"), bits("virtual")) {
+        (Some(physical_bits), Some(linear_bits)) => {
+            results.address_widths = AddressWidths {
+                physical_bits,
+                linear_bits,
+                la57: results.has(CpuFeature::La57),
+                ..Default::default()
+            };
+            results.record(
+                CpuField::AddressWidths,
+                source("address sizes"),
+                Confidence::High,
+            );
         }
+        _ => results.warn(CpuField::AddressWidths, "no address sizes in /proc/cpuinfo"),
     }
-    if let Ok(s) = fs::read_to_string("/sys/devices/system/cpu/cpu0/cpufreq/cpuinfo_max_freq") {
-        if let Ok(khz) = s.trim().parse::<u32>() {
-            return Some(khz / 1000);
+
+    //Arch
+    let (arch, arch_source) = match (value("flags"), value("CPU architecture")) {
+        (Some(flags), _) if flags.split_whitespace().any(|f| f == "lm") => {
+            ("x86_64", source("flags"))
         }
+        (Some(_), _) => ("x86", source("flags")),
+        (None, Some("8")) => ("aarch64", source("CPU architecture")),
+        (None, Some(_)) => ("arm", source("CPU architecture")),
+        (None, None) => (std::env::consts::ARCH, Source::BuildTarget),
+    };
+    results.arch = arch.to_string();
+    results.record(CpuField::Architecture, arch_source, Confidence::Medium);
+
+    if options.wants(CpuField::Tlb) {
+        results.warn(CpuField::Tlb, "TLBs are only reported by CPUID");
     }
-    if let Ok(s) = fs::read_to_string("/proc/cpuinfo") {
-        for line in s.lines() {
-            if let Some(rest) = line.strip_prefix("cpu MHz") {
-                if let Some(val) = rest.split(':').nth(1) {
-                    if let Ok(mhzf) = val.trim().parse::<f64>() {
-                        return Some(mhzf.round() as u32);
-                    }
-                }
+    if options.wants(CpuField::Security) {
+        results.warn(
+            CpuField::Security,
+            "SGX and SEV details are only reported by CPUID",
+        );
+    }
+
+    //Clock
+    if options.wants(CpuField::ClockSpeedTurbo) {
+        match read_sysfs_khz(root, "cpuinfo_max_freq") {
+            Some(khz) => {
+                results.clock_speed_turbo = khz / 1000;
+                results.record(
+                    CpuField::ClockSpeedTurbo,
+                    Source::Sysfs("cpuinfo_max_freq".into()),
+                    Confidence::Medium,
+                );
             }
+            None => results.warn(CpuField::ClockSpeedTurbo, "no sysfs cpuinfo_max_freq"),
         }
     }
-    None
-}
 
-fn read_microcode() -> Option<String> {
-    if let Ok(s) = fs::read_to_string("/proc/cpuinfo") {
-        for line in s.lines() {
-            if let Some(rest) = line.strip_prefix("microcode") {
-                if let Some(val) = rest.split(':').nth(1) {
-                    let t = val.trim();
-                    if t.starts_with("0x") {
-                        return Some(format!("{:#010X}", u32::from_str_radix(&t[2..], 16).ok()?));
-                    } else if let Ok(v) = t.parse::<u32>() {
-                        return Some(format!("{:#010X}", v));
-                    }
-                }
+    //Topology
+    let cpus = sysfs_cpus(root);
+    if options.wants(CpuField::Topology) {
+        match read_sysfs_topology(root, &cpus) {
+            Some((cores, threads)) => {
+                results.cores = cores;
+                results.threads = threads;
+                results.record(
+                    CpuField::Topology,
+                    Source::Sysfs("topology/core_id".into()),
+                    Confidence::High,
+                );
+            }
+            None => {
+                let processors = cpuinfo
+                    .lines()
+                    .filter(|line| line.split(':').next().unwrap_or_default().trim() == "processor")
+                    .count()
+                    .max(1);
+                results.threads = processors;
+                results.cores = value("cpu cores")
+                    .and_then(parse_number)
+                    .map_or(processors, |cores| cores as usize);
+                results.record(CpuField::Topology, source("processor"), Confidence::Low);
             }
         }
     }
-    None
+
+    results
 }
 
-pub fn cache_size(cores: usize, threads: usize) -> (usize, usize, usize) {
-    let max_ext = cpuid(0x8000_0000, 0).0;
-    if max_ext >= 0x8000_001D {
-        cache_size_universal(0x8000_001D, cores, threads)
-    } else {
-        cache_size_universal(4, cores, 0)
-    }
-}
-
-fn cache_size_universal(func: u32, cores: usize, threads_shared: usize) -> (usize, usize, usize) {
-    let mut cache = [0, 0, 0];
-    for i in 0.. {
-        let (eax, ebx, ecx, _) = cpuid(func, i);
-        let cache_type = eax & 0x1F;
-        if cache_type == 0 {
-            break;
-        }
-        let level = (eax >> 5) & 0x7;
-        let line_size = (ebx & 0xFFF) + 1;
-        let partitions = ((ebx >> 12) & 0x3FF) + 1;
-        let ways = ((ebx >> 22) & 0x3FF) + 1;
-        let sets = ecx + 1;
-        let size = ways * partitions * line_size * sets;
-        let shared_logical = if threads_shared > 0 {
-            threads_shared as u32 / (((eax >> 14) & 0xFFF) + 1)
-        } else {
-            1
+/// First value of `key` in `/proc/cpuinfo`, i.e. the one of the first CPU.
+fn cpuinfo_value<'a>(cpuinfo: &'a str, key: &str) -> Option<&'a str> {
+    cpuinfo.lines().find_map(|line| {
+        let (k, v) = line.split_once(':')?;
+        (k.trim() == key).then(|| v.trim())
+    })
+}
+
+fn parse_number(value: &str) -> Option<u32> {
+    match value.strip_prefix("0x") {
+        Some(hex) => u32::from_str_radix(hex, 16).ok(),
+        None => value.parse().ok(),
+    }
+}
+
+fn arm_implementer(implementer: u32) -> &'static str {
+    match implementer {
+        0x41 => "ARM",
+        0x42 => "Broadcom",
+        0x43 => "Cavium",
+        0x46 => "Fujitsu",
+        0x48 => "HiSilicon",
+        0x4E => "NVIDIA",
+        0x50 => "Applied Micro",
+        0x51 => "Qualcomm",
+        0x61 => "Apple",
+        0x69 => "Intel",
+        0x6D => "Microsoft",
+        0xC0 => "Ampere",
+        _ => "Unknown",
+    }
+}
+
+fn sysfs_cpus(root: &Path) -> Vec<usize> {
+    let dir = root.join("sys/devices/system/cpu");
+    if let Ok(s) = fs::read_to_string(dir.join("online")) {
+        let cpus = parse_cpu_list(&s);
+        if !cpus.is_empty() {
+            return cpus;
+        }
+    }
+    let mut cpus: Vec<usize> = fs::read_dir(&dir)
+        .into_iter()
+        .flatten()
+        .flatten()
+        .filter_map(|entry| {
+            entry
+                .file_name()
+                .to_str()?
+                .strip_prefix("cpu")?
+                .parse()
+                .ok()
+        })
+        .collect();
+    cpus.sort_unstable();
+    cpus
+}
+
+/// Hybrid core groups from the `cpu_core` and `cpu_atom` PMU devices, which
+/// only exist on hybrid CPUs.
+fn read_sysfs_core_groups(root: &Path) -> Option<Vec<CoreGroup>> {
+    let mut groups = Vec::new();
+    for (core_type, pmu) in [
+        (CoreType::Performance, "cpu_core"),
+        (CoreType::Efficiency, "cpu_atom"),
+    ] {
+        let cpus = fs::read_to_string(root.join(format!("sys/devices/{pmu}/cpus"))).ok()?;
+        let cpus = parse_cpu_list(&cpus);
+        let cores = read_sysfs_topology(root, &cpus).map_or(cpus.len(), |(cores, _)| cores);
+        groups.push(CoreGroup {
+            core_type,
+            cpus,
+            cores,
+            l1_cache: 0,
+            l2_cache: 0,
+            clock_speed: 0,
+            clock_speed_turbo: 0,
+        });
+    }
+    Some(groups)
+}
+
+/// Highest base and maximum frequency over the CPUs of a group.
+fn read_group_clocks(root: &Path, group: &mut CoreGroup) {
+    for &cpu in &group.cpus {
+        let read = |file: &str| {
+            let path = root.join(format!("sys/devices/system/cpu/cpu{cpu}/cpufreq/{file}"));
+            fs::read_to_string(path).ok()?.trim().parse::<u32>().ok()
         };
-        match (cache_type, level) {
-            (1, 1) => cache[0] += size,
-            (2, 1) => cache[0] += size,
-            (3, 2) => cache[1] += size,
-            (3, 3) => cache[2] += size * shared_logical,
-            _ => {}
+        if let Some(khz) = read("base_frequency") {
+            group.clock_speed = group.clock_speed.max(khz / 1000);
+        }
+        if let Some(khz) = read("cpuinfo_max_freq") {
+            group.clock_speed_turbo = group.clock_speed_turbo.max(khz / 1000);
         }
     }
-    (cache[0] as usize * cores, cache[1] as usize * cores, cache[2] as usize)
+}
+
+fn read_sysfs_topology(root: &Path, cpus: &[usize]) -> Option<(usize, usize)> {
+    let ids = read_sysfs_topology_ids(root, cpus)?;
+    let cores: BTreeSet<TopologyIds> = ids.iter().map(|&(_, ids)| ids).collect();
+    (!cores.is_empty()).then_some((cores.len(), cpus.len()))
+}
+
+/// Package, die, cluster and core IDs of the given CPUs. Kernels without
+/// dies or clusters report one per package.
+fn read_sysfs_topology_ids(root: &Path, cpus: &[usize]) -> Option<Vec<(usize, TopologyIds)>> {
+    let mut ids = Vec::new();
+    for &cpu in cpus {
+        let dir = root.join(format!("sys/devices/system/cpu/cpu{cpu}/topology"));
+        let read = |file: &str| -> Option<u32> {
+            let id: i64 = fs::read_to_string(dir.join(file))
+                .ok()?
+                .trim()
+                .parse()
+                .ok()?;
+            Some(id.max(0) as u32)
+        };
+        let package = read("physical_package_id")?;
+        let core = read("core_id")?;
+        let die = read("die_id").unwrap_or(0);
+        let cluster = read("cluster_id").unwrap_or(0);
+        ids.push((cpu, [package, die, cluster, core]));
+    }
+    Some(ids)
+}
+
+/// Whether two trees have as many packages, cores and threads. Kernels
+/// disagree on what a die is, so dies are not compared.
+fn same_shape(a: &[TopologyNode], b: &[TopologyNode]) -> bool {
+    [
+        TopologyLevel::Package,
+        TopologyLevel::Core,
+        TopologyLevel::Thread,
+    ]
+    .into_iter()
+    .all(|level| {
+        let count = |tree: &[TopologyNode]| tree.iter().map(|n| n.count(level)).sum::<usize>();
+        count(a) == count(b)
+    })
+}
+
+/// APIC IDs the kernel lists for each processor, `None` unless every
+/// processor entry has one.
+fn proc_apic_ids(cpuinfo: &str) -> Option<Vec<(usize, u32)>> {
+    let ids: Vec<(usize, u32)> = cpuinfo
+        .split("\n\n")
+        .filter(|entry| cpuinfo_value(entry, "processor").is_some())
+        .map(|entry| {
+            let cpu = cpuinfo_value(entry, "processor")?.parse().ok()?;
+            let apic_id = cpuinfo_value(entry, "apicid")?.parse().ok()?;
+            Some((cpu, apic_id))
+        })
+        .collect::<Option<_>>()?;
+    (!ids.is_empty()).then_some(ids)
+}
+
+/// Cache instances of the given CPUs, each cache shared by several CPUs
+/// listed once.
+fn read_sysfs_caches(root: &Path, cpus: &[usize]) -> Vec<CacheDescriptor> {
+    let mut caches = BTreeMap::new();
+    for &cpu in cpus {
+        let dir = root.join(format!("sys/devices/system/cpu/cpu{cpu}/cache"));
+        for index in 0.. {
+            let read = |file: &str| fs::read_to_string(dir.join(format!("index{index}/{file}")));
+            let number = |file: &str| read(file).ok().and_then(|s| s.trim().parse::<u32>().ok());
+            let Some(level) = number("level") else {
+                break;
+            };
+            let Some(size) = read("size").ok().and_then(|s| parse_size(&s)) else {
+                continue;
+            };
+            let cache_type = match read("type").unwrap_or_default().trim() {
+                "Data" => CacheType::Data,
+                "Instruction" => CacheType::Instruction,
+                _ => CacheType::Unified,
+            };
+            let shared_cpus = match read("shared_cpu_list").map(|s| parse_cpu_list(&s)) {
+                Ok(shared) if !shared.is_empty() => shared,
+                _ => vec![cpu],
+            };
+            caches
+                .entry((level, cache_type, shared_cpus.clone()))
+                .or_insert_with(|| CacheDescriptor {
+                    level: level as u8,
+                    cache_type,
+                    size,
+                    ways: number("ways_of_associativity").unwrap_or(0),
+                    line_size: number("coherency_line_size").unwrap_or(0),
+                    sets: number("number_of_sets").unwrap_or(0),
+                    partitions: number("physical_line_partition").unwrap_or(1),
+                    inclusive: None,
+                    shared_cpus,
+                });
+        }
+    }
+    caches.into_values().collect()
+}
+
+fn parse_size(size: &str) -> Option<usize> {
+    let size = size.trim();
+    let (digits, multiplier) = match size.as_bytes().last()? {
+        b'K' => (&size[..size.len() - 1], 1 << 10),
+        b'M' => (&size[..size.len() - 1], 1 << 20),
+        b'G' => (&size[..size.len() - 1], 1 << 30),
+        _ => (size, 1),
+    };
+    digits.parse::<usize>().ok().map(|v| v * multiplier)
+}
+
+fn read_sysfs_khz(root: &Path, file: &str) -> Option<u32> {
+    let path = root.join(format!("sys/devices/system/cpu/cpu0/cpufreq/{file}"));
+    fs::read_to_string(path).ok()?.trim().parse().ok()
+}
+
+/// `tsc_khz` of the kernel, only exposed by some kernels.
+fn monotonic_raw_ns() -> u64 {
+    let mut ts = libc::timespec {
+        tv_sec: 0,
+        tv_nsec: 0,
+    };
+    unsafe { libc::clock_gettime(libc::CLOCK_MONOTONIC_RAW, &mut ts) };
+    ts.tv_sec as u64 * 1_000_000_000 + ts.tv_nsec as u64
+}
+
+/// When the kernel keeps time with the TSC, CLOCK_MONOTONIC_RAW is scaled
+/// from its own calibration and a measurement against it is only as noisy
+/// as the window. Otherwise it compares two independent oscillators.
+fn tsc_clocksource_confidence(root: &Path) -> Confidence {
+    let path = root.join("sys/devices/system/clocksource/clocksource0/current_clocksource");
+    match fs::read_to_string(path) {
+        Ok(clocksource) if clocksource.trim() == "tsc" => Confidence::Medium,
+        _ => Confidence::Low,
+    }
+}
+
+fn read_base_mhz(root: &Path, cpuinfo: Option<&str>) -> Option<(u32, Provenance)> {
+    for (file, confidence) in [
+        ("base_frequency", Confidence::High),
+        ("cpuinfo_max_freq", Confidence::Medium),
+    ] {
+        if let Some(khz) = read_sysfs_khz(root, file) {
+            return Some((
+                khz / 1000,
+                Provenance::new(Source::Sysfs(file.into()), confidence),
+            ));
+        }
+    }
+    if let Some(val) = cpuinfo.and_then(|s| cpuinfo_value(s, "cpu MHz"))
+        && let Ok(mhzf) = val.parse::<f64>()
+    {
+        return Some((
+            mhzf.round() as u32,
+            Provenance::new(Source::ProcCpuinfo("cpu MHz".into()), Confidence::Low),
+        ));
+    }
+    None
+}
+
+/// Microcode revision of each processor entry in /proc/cpuinfo.
+fn per_cpu_microcode(cpuinfo: &str) -> BTreeMap<usize, String> {
+    cpuinfo
+        .split("\n\n")
+        .filter_map(|entry| {
+            let cpu = cpuinfo_value(entry, "processor")?.parse().ok()?;
+            Some((cpu, read_microcode(Some(entry)).ok()?))
+        })
+        .collect()
+}
+
+fn read_microcode(cpuinfo: Option<&str>) -> Result<String, &'static str> {
+    let s = cpuinfo.ok_or("/proc/cpuinfo unreadable")?;
+    let t = cpuinfo_value(s, "microcode").ok_or("no microcode entry in /proc/cpuinfo")?;
+    parse_number(t)
+        .map(|v| format!("{:#010X}", v))
+        .ok_or("malformed microcode entry in /proc/cpuinfo")
 }
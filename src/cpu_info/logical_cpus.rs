@@ -0,0 +1,131 @@
+use std::collections::HashMap;
+
+use crate::cpu_info::{
+    Confidence, CoreType, CpuData, CpuFeatures, CpuField, CpuidDecoder, CpuidSource, ProbeOptions,
+    Source,
+};
+
+/// What CPUID reports on one logical CPU, collected by a thread pinned to it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct LogicalCpu {
+    pub(crate) cpu: usize,
+    pub(crate) apic_id: u32,
+    pub(crate) core_type: Option<CoreType>,
+    pub(crate) family: u8,
+    pub(crate) model: u8,
+    pub(crate) stepping: u8,
+    pub(crate) microcode: String,
+    pub(crate) flags: CpuFeatures,
+    pub(crate) odd_flags: CpuFeatures,
+}
+
+impl LogicalCpu {
+    /// Must run on the CPU it describes.
+    pub(crate) fn describe<S: CpuidSource + ?Sized>(cpu: usize, source: &S) -> Self {
+        let decoder = CpuidDecoder::new(source);
+        let signature = decoder.signature();
+        Self {
+            cpu,
+            apic_id: decoder.apic_id(),
+            core_type: decoder.core_type(),
+            family: signature.family,
+            model: signature.model,
+            stepping: signature.stepping,
+            microcode: String::new(),
+            flags: decoder.features(),
+            odd_flags: CpuFeatures::new(),
+        }
+    }
+
+    /// OS number of the logical CPU.
+    pub fn cpu(&self) -> usize {
+        self.cpu
+    }
+
+    /// x2APIC ID, or the initial APIC ID on CPUs without leaf 0xB.
+    pub fn apic_id(&self) -> u32 {
+        self.apic_id
+    }
+
+    /// `None` on non-hybrid CPUs.
+    pub fn core_type(&self) -> Option<CoreType> {
+        self.core_type
+    }
+
+    pub fn family(&self) -> u8 {
+        self.family
+    }
+
+    pub fn model(&self) -> u8 {
+        self.model
+    }
+
+    pub fn stepping(&self) -> u8 {
+        self.stepping
+    }
+
+    /// Microcode revision loaded on this CPU, empty when the OS does not
+    /// report it per CPU.
+    pub fn microcode_version(&self) -> &str {
+        &self.microcode
+    }
+
+    pub fn flags(&self) -> &CpuFeatures {
+        &self.flags
+    }
+
+    /// Features this CPU has while most CPUs lack them, or lacks while most
+    /// have them.
+    pub fn odd_flags(&self) -> &CpuFeatures {
+        &self.odd_flags
+    }
+
+    /// Whether the features of this CPU differ from the others.
+    pub fn differs(&self) -> bool {
+        !self.odd_flags.is_empty()
+    }
+}
+
+/// Runs CPUID on every logical CPU the options give access to.
+pub(crate) fn cpuid_logical_cpus(options: &ProbeOptions) -> Vec<LogicalCpu> {
+    options.per_cpu(|cpu, source| LogicalCpu::describe(cpu, source))
+}
+
+impl CpuData {
+    /// Stores the per-CPU table and marks CPUs whose features differ from
+    /// the feature set most CPUs share.
+    pub(crate) fn set_logical_cpus(&mut self, mut cpus: Vec<LogicalCpu>) {
+        if cpus.is_empty() {
+            self.warn(CpuField::LogicalCpus, "no logical CPU could be pinned");
+            return;
+        }
+
+        let mut counts: HashMap<CpuFeatures, usize> = HashMap::new();
+        for cpu in &cpus {
+            *counts.entry(cpu.flags).or_default() += 1;
+        }
+        //Ties go to the lowest numbered CPU
+        let mut common = cpus[0].flags;
+        for cpu in &cpus {
+            if counts[&cpu.flags] > counts[&common] {
+                common = cpu.flags;
+            }
+        }
+        for cpu in &mut cpus {
+            cpu.odd_flags = (cpu.flags - common) | (common - cpu.flags);
+        }
+
+        self.logical_cpus = cpus;
+        self.record(
+            CpuField::LogicalCpus,
+            Source::CpuidFeatureLeaves,
+            Confidence::High,
+        );
+    }
+
+    /// Logical CPUs whose features differ from the others.
+    pub fn odd_cpus(&self) -> impl Iterator<Item = &LogicalCpu> {
+        self.logical_cpus.iter().filter(|cpu| cpu.differs())
+    }
+}
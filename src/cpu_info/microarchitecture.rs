@@ -0,0 +1,243 @@
+use std::ops::RangeInclusive;
+
+use crate::cpu_info::CpuData;
+
+/// Microarchitecture and product codename of a CPU model.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Microarchitecture {
+    name: &'static str,
+    codename: &'static str,
+    process: &'static str,
+    launch_year: u16,
+}
+
+impl Microarchitecture {
+    /// Core microarchitecture, e.g. "Zen 4" or "Golden Cove + Gracemont".
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    /// Product codename, e.g. "Raphael" or "Alder Lake-S".
+    pub fn codename(&self) -> &'static str {
+        self.codename
+    }
+
+    /// Manufacturing process as named by the foundry.
+    pub fn process(&self) -> &'static str {
+        self.process
+    }
+
+    pub fn launch_year(&self) -> u16 {
+        self.launch_year
+    }
+}
+
+struct Entry {
+    vendor: &'static str,
+    family: u8,
+    models: &'static [u8],
+    steppings: RangeInclusive<u8>,
+    uarch: Microarchitecture,
+}
+
+const ANY: RangeInclusive<u8> = 0..=0xF;
+
+const fn entry(
+    vendor: &'static str,
+    family: u8,
+    models: &'static [u8],
+    steppings: RangeInclusive<u8>,
+    (name, codename, process, launch_year): (&'static str, &'static str, &'static str, u16),
+) -> Entry {
+    Entry {
+        vendor,
+        family,
+        models,
+        steppings,
+        uarch: Microarchitecture {
+            name,
+            codename,
+            process,
+            launch_year,
+        },
+    }
+}
+
+const INTEL: &str = "GenuineIntel";
+const AMD: &str = "AuthenticAMD";
+const HYGON: &str = "HygonGenuine";
+
+#[rustfmt::skip]
+const TABLE: &[Entry] = &[
+    //Intel Core
+    entry(INTEL, 6, &[0x0F, 0x16], ANY, ("Core", "Merom/Conroe", "65 nm", 2006)),
+    entry(INTEL, 6, &[0x17, 0x1D], ANY, ("Penryn", "Penryn/Wolfdale", "45 nm", 2007)),
+    entry(INTEL, 6, &[0x1A, 0x1E, 0x1F, 0x2E], ANY, ("Nehalem", "Bloomfield/Lynnfield", "45 nm", 2008)),
+    entry(INTEL, 6, &[0x25, 0x2C, 0x2F], ANY, ("Westmere", "Westmere", "32 nm", 2010)),
+    entry(INTEL, 6, &[0x2A], ANY, ("Sandy Bridge", "Sandy Bridge", "32 nm", 2011)),
+    entry(INTEL, 6, &[0x2D], ANY, ("Sandy Bridge", "Sandy Bridge-E", "32 nm", 2011)),
+    entry(INTEL, 6, &[0x3A], ANY, ("Ivy Bridge", "Ivy Bridge", "22 nm", 2012)),
+    entry(INTEL, 6, &[0x3E], ANY, ("Ivy Bridge", "Ivy Bridge-E", "22 nm", 2013)),
+    entry(INTEL, 6, &[0x3C, 0x45, 0x46], ANY, ("Haswell", "Haswell", "22 nm", 2013)),
+    entry(INTEL, 6, &[0x3F], ANY, ("Haswell", "Haswell-E", "22 nm", 2014)),
+    entry(INTEL, 6, &[0x3D, 0x47], ANY, ("Broadwell", "Broadwell", "14 nm", 2014)),
+    entry(INTEL, 6, &[0x4F, 0x56], ANY, ("Broadwell", "Broadwell-E", "14 nm", 2016)),
+    entry(INTEL, 6, &[0x4E, 0x5E], ANY, ("Skylake", "Skylake", "14 nm", 2015)),
+    entry(INTEL, 6, &[0x55], 0..=4, ("Skylake", "Skylake-SP", "14 nm", 2017)),
+    entry(INTEL, 6, &[0x55], 5..=7, ("Cascade Lake", "Cascade Lake-SP", "14 nm", 2019)),
+    entry(INTEL, 6, &[0x55], 10..=11, ("Cooper Lake", "Cooper Lake-SP", "14 nm", 2020)),
+    entry(INTEL, 6, &[0x8E], 0..=9, ("Kaby Lake", "Kaby Lake-U/Y", "14 nm", 2016)),
+    entry(INTEL, 6, &[0x8E], 10..=10, ("Kaby Lake", "Kaby Lake-R", "14 nm", 2017)),
+    entry(INTEL, 6, &[0x8E], 11..=11, ("Kaby Lake", "Whiskey Lake-U", "14 nm", 2018)),
+    entry(INTEL, 6, &[0x8E], 12..=15, ("Kaby Lake", "Comet Lake-U", "14 nm", 2019)),
+    entry(INTEL, 6, &[0x9E], 0..=9, ("Kaby Lake", "Kaby Lake-S", "14 nm", 2017)),
+    entry(INTEL, 6, &[0x9E], 10..=15, ("Coffee Lake", "Coffee Lake-S", "14 nm", 2017)),
+    entry(INTEL, 6, &[0xA5, 0xA6], ANY, ("Comet Lake", "Comet Lake", "14 nm", 2020)),
+    entry(INTEL, 6, &[0x66], ANY, ("Palm Cove", "Cannon Lake", "10 nm", 2018)),
+    entry(INTEL, 6, &[0x7D, 0x7E], ANY, ("Sunny Cove", "Ice Lake", "10 nm", 2019)),
+    entry(INTEL, 6, &[0x6A, 0x6C], ANY, ("Sunny Cove", "Ice Lake-SP", "10 nm", 2021)),
+    entry(INTEL, 6, &[0x8C, 0x8D], ANY, ("Willow Cove", "Tiger Lake", "10 nm SuperFin", 2020)),
+    entry(INTEL, 6, &[0xA7], ANY, ("Cypress Cove", "Rocket Lake-S", "14 nm", 2021)),
+    entry(INTEL, 6, &[0x97], ANY, ("Golden Cove + Gracemont", "Alder Lake-S", "Intel 7", 2021)),
+    entry(INTEL, 6, &[0x9A], ANY, ("Golden Cove + Gracemont", "Alder Lake-P", "Intel 7", 2022)),
+    entry(INTEL, 6, &[0xB7, 0xBF], ANY, ("Raptor Cove + Gracemont", "Raptor Lake-S", "Intel 7", 2022)),
+    entry(INTEL, 6, &[0xBA], ANY, ("Raptor Cove + Gracemont", "Raptor Lake-P", "Intel 7", 2023)),
+    entry(INTEL, 6, &[0x8F], ANY, ("Golden Cove", "Sapphire Rapids", "Intel 7", 2023)),
+    entry(INTEL, 6, &[0xCF], ANY, ("Raptor Cove", "Emerald Rapids", "Intel 7", 2023)),
+    entry(INTEL, 6, &[0xAA, 0xAC], ANY, ("Redwood Cove + Crestmont", "Meteor Lake", "Intel 4", 2023)),
+    entry(INTEL, 6, &[0xAD, 0xAE], ANY, ("Redwood Cove", "Granite Rapids", "Intel 3", 2024)),
+    entry(INTEL, 6, &[0xC5, 0xC6], ANY, ("Lion Cove + Skymont", "Arrow Lake", "TSMC N3B", 2024)),
+    entry(INTEL, 6, &[0xBD], ANY, ("Lion Cove + Skymont", "Lunar Lake", "TSMC N3B", 2024)),
+    //Intel Atom and Xeon Phi
+    entry(INTEL, 6, &[0x1C, 0x26], ANY, ("Bonnell", "Diamondville/Pineview", "45 nm", 2008)),
+    entry(INTEL, 6, &[0x37, 0x4D], ANY, ("Silvermont", "Bay Trail/Avoton", "22 nm", 2013)),
+    entry(INTEL, 6, &[0x4C], ANY, ("Airmont", "Cherry Trail", "14 nm", 2015)),
+    entry(INTEL, 6, &[0x5C, 0x5F], ANY, ("Goldmont", "Apollo Lake/Denverton", "14 nm", 2016)),
+    entry(INTEL, 6, &[0x7A], ANY, ("Goldmont Plus", "Gemini Lake", "14 nm", 2017)),
+    entry(INTEL, 6, &[0x86], ANY, ("Tremont", "Snow Ridge", "10 nm", 2020)),
+    entry(INTEL, 6, &[0x96, 0x9C], ANY, ("Tremont", "Elkhart Lake/Jasper Lake", "10 nm", 2021)),
+    entry(INTEL, 6, &[0xBE], ANY, ("Gracemont", "Alder Lake-N", "Intel 7", 2023)),
+    entry(INTEL, 6, &[0xAF], ANY, ("Crestmont", "Sierra Forest", "Intel 3", 2024)),
+    entry(INTEL, 6, &[0x57], ANY, ("Knights Landing", "Knights Landing", "14 nm", 2016)),
+    entry(INTEL, 6, &[0x85], ANY, ("Knights Mill", "Knights Mill", "14 nm", 2017)),
+    //AMD
+    entry(AMD, 0x10, &[0x02, 0x04, 0x05, 0x06, 0x08, 0x09, 0x0A], ANY, ("K10", "Barcelona/Deneb/Thuban", "45 nm", 2007)),
+    entry(AMD, 0x12, &[0x01], ANY, ("K10", "Llano", "32 nm", 2011)),
+    entry(AMD, 0x14, &[0x01, 0x02], ANY, ("Bobcat", "Ontario/Zacate", "40 nm", 2011)),
+    entry(AMD, 0x15, &[0x01], ANY, ("Bulldozer", "Zambezi", "32 nm", 2011)),
+    entry(AMD, 0x15, &[0x02], ANY, ("Piledriver", "Vishera", "32 nm", 2012)),
+    entry(AMD, 0x15, &[0x10, 0x13], ANY, ("Piledriver", "Trinity/Richland", "32 nm", 2012)),
+    entry(AMD, 0x15, &[0x30, 0x38], ANY, ("Steamroller", "Kaveri", "28 nm", 2014)),
+    entry(AMD, 0x15, &[0x60, 0x65], ANY, ("Excavator", "Carrizo/Bristol Ridge", "28 nm", 2015)),
+    entry(AMD, 0x15, &[0x70], ANY, ("Excavator", "Stoney Ridge", "28 nm", 2016)),
+    entry(AMD, 0x16, &[0x00], ANY, ("Jaguar", "Kabini", "28 nm", 2013)),
+    entry(AMD, 0x16, &[0x30], ANY, ("Puma", "Beema/Mullins", "28 nm", 2014)),
+    entry(AMD, 0x17, &[0x01], ANY, ("Zen", "Summit Ridge/Naples", "14 nm", 2017)),
+    entry(AMD, 0x17, &[0x11], ANY, ("Zen", "Raven Ridge", "14 nm", 2018)),
+    entry(AMD, 0x17, &[0x20], ANY, ("Zen", "Dali", "14 nm", 2020)),
+    entry(AMD, 0x17, &[0x08], ANY, ("Zen+", "Pinnacle Ridge", "12 nm", 2018)),
+    entry(AMD, 0x17, &[0x18], ANY, ("Zen+", "Picasso", "12 nm", 2019)),
+    entry(AMD, 0x17, &[0x31], ANY, ("Zen 2", "Rome/Castle Peak", "7 nm", 2019)),
+    entry(AMD, 0x17, &[0x71], ANY, ("Zen 2", "Matisse", "7 nm", 2019)),
+    entry(AMD, 0x17, &[0x60], ANY, ("Zen 2", "Renoir", "7 nm", 2020)),
+    entry(AMD, 0x17, &[0x68], ANY, ("Zen 2", "Lucienne", "7 nm", 2021)),
+    entry(AMD, 0x17, &[0x90], ANY, ("Zen 2", "Van Gogh", "7 nm", 2022)),
+    entry(AMD, 0x17, &[0xA0], ANY, ("Zen 2", "Mendocino", "6 nm", 2022)),
+    entry(AMD, 0x19, &[0x00, 0x01], ANY, ("Zen 3", "Milan", "7 nm", 2021)),
+    entry(AMD, 0x19, &[0x08], ANY, ("Zen 3", "Chagall", "7 nm", 2022)),
+    entry(AMD, 0x19, &[0x21], ANY, ("Zen 3", "Vermeer", "7 nm", 2020)),
+    entry(AMD, 0x19, &[0x50], ANY, ("Zen 3", "Cezanne", "7 nm", 2021)),
+    entry(AMD, 0x19, &[0x40, 0x44], ANY, ("Zen 3+", "Rembrandt", "6 nm", 2022)),
+    entry(AMD, 0x19, &[0x10, 0x11], ANY, ("Zen 4", "Genoa", "5 nm", 2022)),
+    entry(AMD, 0x19, &[0x18], ANY, ("Zen 4", "Storm Peak", "5 nm", 2023)),
+    entry(AMD, 0x19, &[0x61], ANY, ("Zen 4", "Raphael", "5 nm", 2022)),
+    entry(AMD, 0x19, &[0x74, 0x75], ANY, ("Zen 4", "Phoenix", "4 nm", 2023)),
+    entry(AMD, 0x19, &[0x78], ANY, ("Zen 4 + Zen 4c", "Phoenix 2", "4 nm", 2023)),
+    entry(AMD, 0x19, &[0xA0], ANY, ("Zen 4c", "Bergamo/Siena", "5 nm", 2023)),
+    entry(AMD, 0x1A, &[0x00, 0x01, 0x02], ANY, ("Zen 5", "Turin", "4 nm", 2024)),
+    entry(AMD, 0x1A, &[0x10, 0x11], ANY, ("Zen 5c", "Turin Dense", "3 nm", 2024)),
+    entry(AMD, 0x1A, &[0x20, 0x24], ANY, ("Zen 5 + Zen 5c", "Strix Point", "4 nm", 2024)),
+    entry(AMD, 0x1A, &[0x44], ANY, ("Zen 5", "Granite Ridge", "4 nm", 2024)),
+    entry(AMD, 0x1A, &[0x70], ANY, ("Zen 5", "Strix Halo", "4 nm", 2025)),
+    //Hygon
+    entry(HYGON, 0x18, &[0x00, 0x01], ANY, ("Zen", "Dhyana", "14 nm", 2018)),
+];
+
+impl CpuData {
+    /// Looks up the microarchitecture of this CPU in the built-in table.
+    /// `None` for unknown models and non-x86 CPUs.
+    pub fn microarchitecture(&self) -> Option<Microarchitecture> {
+        TABLE
+            .iter()
+            .find(|e| {
+                e.vendor == self.vendor
+                    && e.family == self.family
+                    && e.models.contains(&self.model)
+                    && e.steppings.contains(&self.stepping)
+            })
+            .map(|e| e.uarch)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lookup(
+        vendor: &str,
+        family: u8,
+        model: u8,
+        stepping: u8,
+    ) -> Option<(&'static str, &'static str)> {
+        let data = CpuData {
+            vendor: vendor.to_string(),
+            family,
+            model,
+            stepping,
+            ..CpuData::default()
+        };
+        data.microarchitecture().map(|u| (u.name(), u.codename()))
+    }
+
+    #[test]
+    fn known_models() {
+        let cases = [
+            (
+                INTEL,
+                6,
+                0x9A,
+                4,
+                ("Golden Cove + Gracemont", "Alder Lake-P"),
+            ),
+            (INTEL, 6, 0x8F, 8, ("Golden Cove", "Sapphire Rapids")),
+            (INTEL, 6, 0x3C, 3, ("Haswell", "Haswell")),
+            (AMD, 0x19, 0x61, 2, ("Zen 4", "Raphael")),
+            (AMD, 0x17, 0x71, 0, ("Zen 2", "Matisse")),
+            (HYGON, 0x18, 0x00, 1, ("Zen", "Dhyana")),
+        ];
+        for (vendor, family, model, stepping, expected) in cases {
+            assert_eq!(
+                lookup(vendor, family, model, stepping),
+                Some(expected),
+                "{model:#X}"
+            );
+        }
+    }
+
+    #[test]
+    fn stepping_splits_a_model() {
+        //Model 0x55 is Skylake-SP, Cascade Lake or Cooper Lake by stepping
+        assert_eq!(lookup(INTEL, 6, 0x55, 4).unwrap().1, "Skylake-SP");
+        assert_eq!(lookup(INTEL, 6, 0x55, 7).unwrap().1, "Cascade Lake-SP");
+        assert_eq!(lookup(INTEL, 6, 0x55, 11).unwrap().1, "Cooper Lake-SP");
+        assert_eq!(lookup(INTEL, 6, 0x55, 8), None);
+    }
+
+    #[test]
+    fn unknown_models() {
+        assert_eq!(lookup(INTEL, 6, 0x01, 0), None);
+        //Same family and model as Raphael, other vendor
+        assert_eq!(lookup(INTEL, 0x19, 0x61, 2), None);
+        assert_eq!(lookup("", 0, 0, 0), None);
+    }
+}
@@ -0,0 +1,111 @@
+use crate::cpu_info::{CpuData, CpuFeature, CpuFeatures};
+
+/// Power and clock capabilities from CPUID leaves 0x6 and 0x80000007.
+pub const POWER_FEATURES: [CpuFeature; 16] = [
+    CpuFeature::ConstantTsc,
+    CpuFeature::Ida,
+    CpuFeature::Cpb,
+    CpuFeature::Hwp,
+    CpuFeature::HwpNotify,
+    CpuFeature::HwpActWindow,
+    CpuFeature::HwpEpp,
+    CpuFeature::HwpPkgReq,
+    CpuFeature::HwpHighestPerfChange,
+    CpuFeature::HwPstate,
+    CpuFeature::Arat,
+    CpuFeature::Epb,
+    CpuFeature::Dtherm,
+    CpuFeature::Pln,
+    CpuFeature::Pts,
+    CpuFeature::Aperfmperf,
+];
+
+impl CpuData {
+    /// The `POWER_FEATURES` this CPU has.
+    pub fn power_features(&self) -> CpuFeatures {
+        POWER_FEATURES
+            .into_iter()
+            .filter(|&feature| self.has(feature))
+            .collect()
+    }
+
+    /// Whether the TSC ticks at a constant rate in every P-, C- and T-state,
+    /// so it can be used as a clock.
+    pub fn has_invariant_tsc(&self) -> bool {
+        self.has(CpuFeature::ConstantTsc)
+    }
+
+    /// Intel Turbo Boost or AMD Core Performance Boost.
+    pub fn has_turbo(&self) -> bool {
+        self.has(CpuFeature::Ida) || self.has(CpuFeature::Cpb)
+    }
+
+    /// Hardware-controlled P-states (Intel Speed Shift).
+    pub fn has_hwp(&self) -> bool {
+        self.has(CpuFeature::Hwp)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cpu_info::CpuidDecoder;
+    use crate::cpu_info::x86_cpuid::tests::intel;
+
+    fn data(leaves: &[(u32, u32, [u32; 4])]) -> CpuData {
+        let mut leaves = leaves.to_vec();
+        leaves.push((0x8000_0000, 0, [0x8000_0007, 0, 0, 0]));
+        CpuData {
+            flags: CpuidDecoder::new(&intel(6, &leaves)).features(),
+            ..CpuData::default()
+        }
+    }
+
+    fn power_features(data: &CpuData) -> Vec<CpuFeature> {
+        let present = data.power_features();
+        POWER_FEATURES
+            .into_iter()
+            .filter(|&feature| present.has(feature))
+            .collect()
+    }
+
+    #[test]
+    fn thermal_and_power_leaf() {
+        //Leaf 0x6 EAX: DTS, Turbo Boost, ARAT, HWP and EPP. ECX: APERF/MPERF, EPB
+        let data = data(&[
+            (6, 0, [0b100_1000_0111, 0, 0b1001, 0]),
+            (0x8000_0007, 0, [0, 0, 0, 1 << 8]),
+        ]);
+        assert_eq!(
+            power_features(&data),
+            [
+                CpuFeature::ConstantTsc,
+                CpuFeature::Ida,
+                CpuFeature::Hwp,
+                CpuFeature::HwpEpp,
+                CpuFeature::Arat,
+                CpuFeature::Epb,
+                CpuFeature::Dtherm,
+                CpuFeature::Aperfmperf,
+            ]
+        );
+        assert!(data.has_invariant_tsc() && data.has_turbo() && data.has_hwp());
+    }
+
+    #[test]
+    fn advanced_power_management_leaf() {
+        //Leaf 0x80000007 EDX: hardware P-states, invariant TSC, Core Performance Boost
+        let data = data(&[(0x8000_0007, 0, [0, 0, 0, 0b111 << 7])]);
+        assert_eq!(
+            power_features(&data),
+            [
+                CpuFeature::ConstantTsc,
+                CpuFeature::Cpb,
+                CpuFeature::HwPstate
+            ]
+        );
+        assert!(data.has_invariant_tsc() && data.has_turbo());
+        assert!(!data.has_hwp());
+        assert!(!CpuData::default().has_turbo());
+    }
+}
@@ -0,0 +1,66 @@
+use std::{fmt, io, path::PathBuf};
+
+use crate::cpu_info::CpuField;
+
+/// Fatal failure that prevents any `CpuData` from being produced.
+#[derive(Debug)]
+pub enum ProbeError {
+    CpuidUnavailable,
+    Io(PathBuf, io::Error),
+    InvalidDump { line: usize, reason: String },
+}
+
+impl fmt::Display for ProbeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ProbeError::CpuidUnavailable => {
+                write!(f, "CPUID instruction is not available on this CPU")
+            }
+            ProbeError::Io(path, e) => write!(f, "{}: {e}", path.display()),
+            ProbeError::InvalidDump { line, reason } => {
+                write!(f, "invalid CPUID dump at line {line}: {reason}")
+            }
+        }
+    }
+}
+
+impl std::error::Error for ProbeError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ProbeError::Io(_, e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+/// Non-fatal problem encountered while probing a single field. The field
+/// keeps its default value, which should be read as "unknown" rather than zero.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ProbeWarning {
+    field: CpuField,
+    reason: String,
+}
+
+impl ProbeWarning {
+    pub fn new(field: CpuField, reason: impl Into<String>) -> Self {
+        Self {
+            field,
+            reason: reason.into(),
+        }
+    }
+
+    pub fn field(&self) -> CpuField {
+        self.field
+    }
+
+    pub fn reason(&self) -> &str {
+        &self.reason
+    }
+}
+
+impl fmt::Display for ProbeWarning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} unavailable: {}", self.field, self.reason)
+    }
+}
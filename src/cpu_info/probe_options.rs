@@ -0,0 +1,265 @@
+use std::{
+    collections::BTreeSet,
+    path::{Path, PathBuf},
+};
+
+use crate::cpu_info::{
+    CpuData, CpuField, CpuInfo, CpuidDump, CpuidSource, NativeCpuid, ProbeError, cpu_affinity,
+};
+
+/// Fields that can be switched off. Vendor, brand, architecture and the
+/// family/model/stepping signature are cheap and always probed.
+const OPTIONAL_FIELDS: [CpuField; 11] = [
+    CpuField::Flags,
+    CpuField::XsaveState,
+    CpuField::Topology,
+    CpuField::CoreTypes,
+    CpuField::Caches,
+    CpuField::Tlb,
+    CpuField::ClockSpeed,
+    CpuField::ClockSpeedTurbo,
+    CpuField::TscFrequency,
+    CpuField::Microcode,
+    CpuField::Security,
+];
+
+const CLOCK_FIELDS: [CpuField; 3] = [
+    CpuField::ClockSpeed,
+    CpuField::ClockSpeedTurbo,
+    CpuField::TscFrequency,
+];
+
+#[derive(Debug, Clone, Default)]
+enum CpuidMode {
+    #[default]
+    Native,
+    Dump(CpuidDump),
+    Disabled,
+}
+
+/// Controls where and how a `CpuInfo` backend reads CPU information, and
+/// which parts of it are probed at all.
+///
+/// Everything is probed by default. The first `with_*` call narrows probing
+/// down to the selected subsystems, `without_*` drops one:
+///
+/// ```no_run
+/// # use cpu_info::{CpuFeature, CpuInfo};
+/// let cpu = cpu_info::builder().with_flags().build()?;
+/// let avx2 = cpu.static_data().has(CpuFeature::Avx2);
+/// # Ok::<(), cpu_info::ProbeError>(())
+/// ```
+///
+/// Fields that were not probed carry a "not requested" warning.
+#[derive(Debug, Clone)]
+pub struct ProbeOptions {
+    root: PathBuf,
+    cpuid: CpuidMode,
+    fields: Option<BTreeSet<CpuField>>,
+    per_cpu_table: bool,
+    tsc_calibration: bool,
+}
+
+impl Default for ProbeOptions {
+    fn default() -> Self {
+        Self {
+            root: PathBuf::from("/"),
+            cpuid: CpuidMode::default(),
+            fields: None,
+            per_cpu_table: false,
+            tsc_calibration: false,
+        }
+    }
+}
+
+impl ProbeOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Reads `/proc` and `/sys` below `root` instead of `/`, e.g. a captured
+    /// fixture tree or a host's filesystem mounted into a container. Only
+    /// used by the Linux backend.
+    pub fn with_root(mut self, root: impl Into<PathBuf>) -> Self {
+        self.root = root.into();
+        self
+    }
+
+    /// Replays CPUID leaves from a dump instead of executing the instruction.
+    pub fn with_cpuid_dump(mut self, dump: CpuidDump) -> Self {
+        self.cpuid = CpuidMode::Dump(dump);
+        self
+    }
+
+    /// Skips CPUID entirely, so everything comes from the OS. Pair this with
+    /// `with_root` when the fixture tree was not captured on this CPU.
+    pub fn without_cpuid(mut self) -> Self {
+        self.cpuid = CpuidMode::Disabled;
+        self
+    }
+
+    /// Feature flags and the OS-enabled register state behind them.
+    pub fn with_flags(self) -> Self {
+        self.select(&[CpuField::Flags, CpuField::XsaveState])
+    }
+
+    pub fn without_flags(self) -> Self {
+        self.deselect(&[CpuField::Flags, CpuField::XsaveState])
+    }
+
+    /// Topology and the core types of hybrid CPUs.
+    pub fn with_topology(self) -> Self {
+        self.select(&[CpuField::Topology, CpuField::CoreTypes])
+    }
+
+    pub fn without_topology(self) -> Self {
+        self.deselect(&[CpuField::Topology, CpuField::CoreTypes])
+    }
+
+    /// Cache and TLB geometry.
+    pub fn with_caches(self) -> Self {
+        self.select(&[CpuField::Caches, CpuField::Tlb])
+    }
+
+    pub fn without_caches(self) -> Self {
+        self.deselect(&[CpuField::Caches, CpuField::Tlb])
+    }
+
+    /// Base and turbo clock speed and the TSC frequency.
+    pub fn with_clocks(self) -> Self {
+        self.select(&CLOCK_FIELDS)
+    }
+
+    pub fn without_clocks(self) -> Self {
+        self.deselect(&CLOCK_FIELDS)
+    }
+
+    pub fn with_microcode(self) -> Self {
+        self.select(&[CpuField::Microcode])
+    }
+
+    pub fn without_microcode(self) -> Self {
+        self.deselect(&[CpuField::Microcode])
+    }
+
+    /// SGX enclave page cache and AMD memory encryption details. The
+    /// security feature bits themselves are flags.
+    pub fn with_security(self) -> Self {
+        self.select(&[CpuField::Security])
+    }
+
+    pub fn without_security(self) -> Self {
+        self.deselect(&[CpuField::Security])
+    }
+
+    /// Also runs CPUID on a thread pinned to each logical CPU and keeps the
+    /// results as `CpuData::logical_cpus`. Off by default, and unaffected by
+    /// the other `with_*` and `without_*` calls.
+    pub fn with_per_cpu_table(mut self) -> Self {
+        self.per_cpu_table = true;
+        self
+    }
+
+    /// Calibrates the host's TSC for about 20 ms when neither CPUID nor the
+    /// OS reports its frequency. Off by default, and unaffected by the other
+    /// `with_*` and `without_*` calls.
+    pub fn with_tsc_calibration(mut self) -> Self {
+        self.tsc_calibration = true;
+        self
+    }
+
+    fn select(mut self, fields: &[CpuField]) -> Self {
+        self.fields
+            .get_or_insert_with(BTreeSet::new)
+            .extend(fields.iter().copied());
+        self
+    }
+
+    fn deselect(mut self, fields: &[CpuField]) -> Self {
+        let selected = self
+            .fields
+            .get_or_insert_with(|| OPTIONAL_FIELDS.into_iter().collect());
+        for field in fields {
+            selected.remove(field);
+        }
+        self
+    }
+
+    /// Whether `field` is going to be probed.
+    pub fn wants(&self, field: CpuField) -> bool {
+        if field == CpuField::LogicalCpus {
+            return self.per_cpu_table;
+        }
+        match &self.fields {
+            Some(fields) => !OPTIONAL_FIELDS.contains(&field) || fields.contains(&field),
+            None => true,
+        }
+    }
+
+    /// Probes the host CPU with these options.
+    pub fn build(&self) -> Result<impl CpuInfo + use<>, ProbeError> {
+        crate::host_cpu_info_with(self)
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// Calls `f` with a CPUID source for every logical CPU: the instruction
+    /// on a thread pinned to each online CPU, or each CPU of a dump. CPUs that
+    /// cannot be pinned are left out.
+    pub(crate) fn per_cpu<T, F>(&self, f: F) -> Vec<T>
+    where
+        T: Send,
+        F: Fn(usize, &dyn CpuidSource) -> T + Sync,
+    {
+        match &self.cpuid {
+            CpuidMode::Native => cpu_affinity::online_cpus()
+                .into_iter()
+                .filter_map(|cpu| cpu_affinity::run_on_cpu(cpu, || f(cpu, &NativeCpuid)))
+                .collect(),
+            CpuidMode::Dump(dump) => dump
+                .cpus()
+                .filter_map(|cpu| dump.cpu(cpu).map(|source| f(cpu, &source)))
+                .collect(),
+            CpuidMode::Disabled => Vec::new(),
+        }
+    }
+
+    /// Whether the CPU being probed is the one running this code, i.e. no
+    /// CPUID dump is replayed. The Linux backend also needs the root to be `/`.
+    pub(crate) fn probes_host(&self) -> bool {
+        !matches!(self.cpuid, CpuidMode::Dump(_))
+    }
+
+    /// Whether the host's TSC may be calibrated, which never applies to a
+    /// replayed dump.
+    pub(crate) fn calibrates_tsc(&self) -> bool {
+        self.tsc_calibration && self.probes_host()
+    }
+
+    /// CPUID source selected by these options. A dump replays its lowest
+    /// numbered CPU.
+    pub(crate) fn cpuid_source(&self) -> Option<Box<dyn CpuidSource + '_>> {
+        match &self.cpuid {
+            CpuidMode::Native => Some(Box::new(NativeCpuid)),
+            CpuidMode::Dump(dump) => dump
+                .cpus()
+                .next()
+                .and_then(|cpu| dump.cpu(cpu))
+                .map(|cpu| Box::new(cpu) as Box<dyn CpuidSource>),
+            CpuidMode::Disabled => None,
+        }
+    }
+}
+
+impl CpuData {
+    /// Marks fields `options` did not ask for as unknown.
+    pub(crate) fn skip_unrequested(&mut self, options: &ProbeOptions) {
+        for field in OPTIONAL_FIELDS.into_iter().chain([CpuField::LogicalCpus]) {
+            if !options.wants(field) {
+                self.warn(field, "not requested");
+            }
+        }
+    }
+}
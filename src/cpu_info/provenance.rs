@@ -0,0 +1,84 @@
+use std::fmt;
+
+/// Where a `CpuData` value was read from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum Source {
+    Cpuid { leaf: u32 },
+    CpuidFeatureLeaves,
+    Xgetbv,
+    Sysfs(String),
+    ProcCpuinfo(String),
+    Registry(String),
+    OsApi(String),
+    Calibration(String),
+    BuildTarget,
+}
+
+impl fmt::Display for Source {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Source::Cpuid { leaf } => write!(f, "CPUID leaf {leaf:#x}"),
+            Source::CpuidFeatureLeaves => write!(f, "CPUID feature leaves"),
+            Source::Xgetbv => write!(f, "XGETBV XCR0"),
+            Source::Sysfs(file) => write!(f, "sysfs {file}"),
+            Source::ProcCpuinfo(key) => write!(f, "/proc/cpuinfo {key}"),
+            Source::Registry(value) => write!(f, "registry {value}"),
+            Source::OsApi(name) => write!(f, "{name}"),
+            Source::Calibration(clock) => write!(f, "calibrated against {clock}"),
+            Source::BuildTarget => write!(f, "build target"),
+        }
+    }
+}
+
+/// How much a value can be trusted.
+/// `High` is reported as-is by the hardware or OS, `Medium` is computed from
+/// or substituted by a closely related value and `Low` is a heuristic or an
+/// instantaneous reading that can change between probes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum Confidence {
+    Low,
+    Medium,
+    High,
+}
+
+impl fmt::Display for Confidence {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Confidence::Low => "low",
+            Confidence::Medium => "medium",
+            Confidence::High => "high",
+        };
+        write!(f, "{name}")
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Provenance {
+    source: Source,
+    confidence: Confidence,
+}
+
+impl Provenance {
+    pub fn new(source: Source, confidence: Confidence) -> Self {
+        Self { source, confidence }
+    }
+
+    pub fn source(&self) -> &Source {
+        &self.source
+    }
+
+    pub fn confidence(&self) -> Confidence {
+        self.confidence
+    }
+}
+
+impl fmt::Display for Provenance {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.source)
+    }
+}
@@ -0,0 +1,300 @@
+use crate::cpu_info::{CpuData, CpuFeature, CpuFeatures, CpuidDecoder, CpuidSource};
+
+/// Protection, trusted-execution, memory-encryption and speculation-control
+/// features.
+pub const SECURITY_FEATURES: [CpuFeature; 26] = [
+    CpuFeature::Smep,
+    CpuFeature::Smap,
+    CpuFeature::Umip,
+    CpuFeature::Pku,
+    CpuFeature::Ospke,
+    CpuFeature::Shstk,
+    CpuFeature::Ibt,
+    CpuFeature::Sgx,
+    CpuFeature::SgxLc,
+    CpuFeature::TdxGuest,
+    CpuFeature::Tme,
+    CpuFeature::Sme,
+    CpuFeature::Sev,
+    CpuFeature::SevEs,
+    CpuFeature::SevSnp,
+    CpuFeature::SpecCtrl,
+    CpuFeature::IntelStibp,
+    CpuFeature::SpecCtrlSsbd,
+    CpuFeature::MdClear,
+    CpuFeature::FlushL1d,
+    CpuFeature::ArchCapabilities,
+    CpuFeature::AmdIbpb,
+    CpuFeature::AmdIbrs,
+    CpuFeature::AmdStibp,
+    CpuFeature::AmdSsbd,
+    CpuFeature::VirtSsbd,
+];
+
+/// One section of the SGX enclave page cache.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct EpcSection {
+    pub(crate) base: u64,
+    pub(crate) size: u64,
+}
+
+impl EpcSection {
+    /// Physical address of the section.
+    pub fn base(&self) -> u64 {
+        self.base
+    }
+
+    /// Size in bytes.
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+}
+
+/// SGX capabilities from CPUID leaf 0x12.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Sgx {
+    pub(crate) sgx1: bool,
+    pub(crate) sgx2: bool,
+    pub(crate) max_enclave_bits_32: u8,
+    pub(crate) max_enclave_bits_64: u8,
+    pub(crate) epc_sections: Vec<EpcSection>,
+}
+
+impl Sgx {
+    pub fn sgx1(&self) -> bool {
+        self.sgx1
+    }
+
+    /// Dynamic enclave memory management (EDMM).
+    pub fn sgx2(&self) -> bool {
+        self.sgx2
+    }
+
+    /// Largest enclave in bytes outside of 64-bit mode.
+    pub fn max_enclave_size_32(&self) -> u64 {
+        1u64.checked_shl(self.max_enclave_bits_32 as u32)
+            .unwrap_or(0)
+    }
+
+    /// Largest enclave in bytes in 64-bit mode.
+    pub fn max_enclave_size_64(&self) -> u64 {
+        1u64.checked_shl(self.max_enclave_bits_64 as u32)
+            .unwrap_or(0)
+    }
+
+    pub fn epc_sections(&self) -> &[EpcSection] {
+        &self.epc_sections
+    }
+
+    /// Total size in bytes of all EPC sections.
+    pub fn epc_size(&self) -> u64 {
+        self.epc_sections.iter().map(EpcSection::size).sum()
+    }
+}
+
+/// AMD memory encryption (SME/SEV) parameters from CPUID leaf 0x8000001F.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct MemoryEncryption {
+    pub(crate) c_bit: u8,
+    pub(crate) encrypted_guests: u32,
+    pub(crate) min_sev_asid: u32,
+}
+
+impl MemoryEncryption {
+    /// Page table bit that marks a page as encrypted.
+    pub fn c_bit(&self) -> u8 {
+        self.c_bit
+    }
+
+    /// Number of encrypted guests that can run at the same time, which is
+    /// also the highest ASID usable for them.
+    pub fn encrypted_guests(&self) -> u32 {
+        self.encrypted_guests
+    }
+
+    /// Lowest ASID for plain SEV guests. The ASIDs below it are reserved for
+    /// SEV-ES and SEV-SNP guests.
+    pub fn min_sev_asid(&self) -> u32 {
+        self.min_sev_asid
+    }
+
+    /// Number of ASIDs for plain SEV guests.
+    pub fn sev_asids(&self) -> u32 {
+        self.encrypted_guests
+            .saturating_add(1)
+            .saturating_sub(self.min_sev_asid.max(1))
+    }
+
+    /// Number of ASIDs for SEV-ES and SEV-SNP guests.
+    pub fn sev_es_asids(&self) -> u32 {
+        self.min_sev_asid
+            .saturating_sub(1)
+            .min(self.encrypted_guests)
+    }
+}
+
+impl<S: CpuidSource + ?Sized> CpuidDecoder<'_, S> {
+    /// SGX capabilities and EPC sections from leaf 0x12, `None` without SGX.
+    pub fn sgx(&self) -> Option<Sgx> {
+        let sgx = self.leaf(7, 0).unwrap_or_default().ebx & (1 << 2) != 0;
+        let r = self.leaf(0x12, 0).filter(|_| sgx)?;
+        let mut epc_sections = Vec::new();
+        for subleaf in 2..16 {
+            let section = self.leaf(0x12, subleaf).unwrap_or_default();
+            //Type 1 is an EPC section, 0 ends the list
+            match section.eax & 0xF {
+                0 => break,
+                1 => epc_sections.push(EpcSection {
+                    base: ((section.ebx as u64 & 0xF_FFFF) << 32)
+                        | (section.eax & 0xFFFF_F000) as u64,
+                    size: ((section.edx as u64 & 0xF_FFFF) << 32)
+                        | (section.ecx & 0xFFFF_F000) as u64,
+                }),
+                _ => {}
+            }
+        }
+        Some(Sgx {
+            sgx1: r.eax & 1 != 0,
+            sgx2: r.eax & (1 << 1) != 0,
+            max_enclave_bits_32: (r.edx & 0xFF) as u8,
+            max_enclave_bits_64: ((r.edx >> 8) & 0xFF) as u8,
+            epc_sections,
+        })
+    }
+
+    /// SME/SEV parameters from leaf 0x8000001F, `None` without either.
+    pub fn memory_encryption(&self) -> Option<MemoryEncryption> {
+        let r = self.leaf(0x8000_001F, 0).filter(|r| r.eax & 0b11 != 0)?;
+        Some(MemoryEncryption {
+            c_bit: (r.ebx & 0x3F) as u8,
+            encrypted_guests: r.ecx,
+            min_sev_asid: r.edx,
+        })
+    }
+
+    /// Whether this is an Intel TDX trust domain, which identifies itself
+    /// in leaf 0x21.
+    pub fn is_tdx_guest(&self) -> bool {
+        self.leaf(0x21, 0).is_some_and(|r| {
+            let mut id = Vec::with_capacity(12);
+            for register in [r.ebx, r.edx, r.ecx] {
+                id.extend_from_slice(&register.to_le_bytes());
+            }
+            id == b"IntelTDX    "
+        })
+    }
+}
+
+impl CpuData {
+    /// The `SECURITY_FEATURES` this CPU has.
+    pub fn security_features(&self) -> CpuFeatures {
+        SECURITY_FEATURES
+            .into_iter()
+            .filter(|&feature| self.has(feature))
+            .collect()
+    }
+
+    /// Indirect branch restricted speculation, on Intel or AMD.
+    pub fn has_ibrs(&self) -> bool {
+        self.has(CpuFeature::SpecCtrl) || self.has(CpuFeature::AmdIbrs)
+    }
+
+    /// Indirect branch predictor barrier, which Intel enumerates together
+    /// with IBRS.
+    pub fn has_ibpb(&self) -> bool {
+        self.has(CpuFeature::SpecCtrl) || self.has(CpuFeature::AmdIbpb)
+    }
+
+    /// Single thread indirect branch predictors, on Intel or AMD.
+    pub fn has_stibp(&self) -> bool {
+        self.has(CpuFeature::IntelStibp) || self.has(CpuFeature::AmdStibp)
+    }
+
+    /// Speculative store bypass disable, also through the virtualized
+    /// `VIRT_SPEC_CTRL` of AMD guests.
+    pub fn has_ssbd(&self) -> bool {
+        self.has(CpuFeature::SpecCtrlSsbd)
+            || self.has(CpuFeature::AmdSsbd)
+            || self.has(CpuFeature::VirtSsbd)
+    }
+
+    /// Control-flow enforcement: both the shadow stack and indirect branch
+    /// tracking.
+    pub fn has_cet(&self) -> bool {
+        self.has(CpuFeature::Shstk) && self.has(CpuFeature::Ibt)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cpu_info::x86_cpuid::tests::{intel, table};
+
+    #[test]
+    fn epc_sections() {
+        let source = intel(
+            0x12,
+            &[
+                (7, 0, [0, 1 << 2, 0, 0]),
+                (0x12, 0, [0b11, 0, 0, 0x2420]),
+                //Low bits of EAX and ECX hold the type and properties
+                (0x12, 2, [0x7000_0001, 0, 0x0780_0001, 0]),
+                (0x12, 3, [0x8000_0001, 1, 0, 2]),
+            ],
+        );
+        let sgx = CpuidDecoder::new(&source).sgx().unwrap();
+        assert!(sgx.sgx1() && sgx.sgx2());
+        assert_eq!(
+            (sgx.max_enclave_size_32(), sgx.max_enclave_size_64()),
+            (1 << 32, 1 << 36)
+        );
+        let sections: Vec<(u64, u64)> = sgx
+            .epc_sections()
+            .iter()
+            .map(|s| (s.base(), s.size()))
+            .collect();
+        assert_eq!(
+            sections,
+            [(0x7000_0000, 0x0780_0000), (0x1_8000_0000, 0x2_0000_0000)]
+        );
+        assert_eq!(sgx.epc_size(), 0x2_0780_0000);
+
+        //Leaf 0x12 means nothing without the SGX feature bit
+        let source = intel(0x12, &[(0x12, 0, [0b11, 0, 0, 0x2420])]);
+        assert_eq!(CpuidDecoder::new(&source).sgx(), None);
+    }
+
+    #[test]
+    fn memory_encryption_leaf() {
+        let source = table(&[
+            (0x8000_0000, 0, [0x8000_001F, 0, 0, 0]),
+            (0x8000_001F, 0, [0b1011, 51, 509, 100]),
+        ]);
+        let encryption = CpuidDecoder::new(&source).memory_encryption().unwrap();
+        assert_eq!(encryption.c_bit(), 51);
+        assert_eq!(
+            (encryption.sev_asids(), encryption.sev_es_asids()),
+            (410, 99)
+        );
+    }
+
+    #[test]
+    fn sev_asid_ranges() {
+        let asids = |encrypted_guests, min_sev_asid| {
+            let encryption = MemoryEncryption {
+                c_bit: 51,
+                encrypted_guests,
+                min_sev_asid,
+            };
+            (encryption.sev_asids(), encryption.sev_es_asids())
+        };
+        //No ASIDs reserved for SEV-ES, ASID 0 belongs to the host
+        assert_eq!(asids(509, 0), (509, 0));
+        assert_eq!(asids(509, 1), (509, 0));
+        //Every ASID reserved for SEV-ES
+        assert_eq!(asids(509, 600), (0, 509));
+    }
+}
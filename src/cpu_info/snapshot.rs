@@ -0,0 +1,78 @@
+use crate::cpu_info::CpuData;
+
+/// Version of the serialized `CpuSnapshot` layout.
+///
+/// Bumped whenever a field is removed, renamed or changes meaning. Adding a
+/// new field does not bump it: every field of `CpuData` falls back to its
+/// default when missing, so older snapshots keep loading into newer types.
+pub const SCHEMA_VERSION: u32 = 3;
+
+/// A `CpuData` tagged with the schema version it was written with, meant to
+/// be stored and loaded back with any serde format.
+///
+/// Schema v3:
+/// - `schema_version`: integer
+/// - `data.brand`, `data.vendor`, `data.arch`, `data.microcode`: string
+/// - `data.family`, `data.model`, `data.stepping`: integer (0-255)
+/// - `data.hypervisor`: `{ vendor, signature, max_leaf, version, features }`
+///   or null on bare metal
+/// - `data.address_widths`: `{ physical_bits, linear_bits, guest_physical_bits,
+///   encryption_reduction, la57 }`
+/// - `data.caches`: list of `{ level, cache_type, size, ways, line_size, sets,
+///   partitions, inclusive, shared_cpus }`, sizes in bytes, `ways` 0 when
+///   fully associative, `inclusive` null when unknown (v2 stored `l1_cache`,
+///   `l2_cache` and `l3_cache` totals instead)
+/// - `data.tlbs`: list of `{ level, tlb_type, page_sizes, entries, ways }`,
+///   page sizes as `4k`, `2m`, `4m` or `1g`, `ways` 0 when fully associative
+/// - `data.cores`, `data.threads`: integer, totals over all packages
+/// - `data.topology`: list of packages, each node `{ level, id, cpus,
+///   children }` with `level` one of `package`, `die`, `module`, `core` or
+///   `thread`
+/// - `data.core_groups`: list of `{ core_type, cpus, cores, l1_cache, l2_cache,
+///   clock_speed, clock_speed_turbo }`, empty unless hybrid
+/// - `data.logical_cpus`: list of `{ cpu, apic_id, core_type, family, model,
+///   stepping, microcode, flags, odd_flags }`, empty unless requested
+/// - `data.flags`: list of `/proc/cpuinfo` flag names (v1 used ad-hoc names)
+/// - `data.enabled_state`, `data.usable_state`: integer, XCR0 bitmask
+/// - `data.clock_speed`, `data.clock_speed_turbo`: integer, MHz
+/// - `data.tsc_frequency`: integer, Hz
+/// - `data.sgx`: `{ sgx1, sgx2, max_enclave_bits_32, max_enclave_bits_64,
+///   epc_sections }` with sections as `{ base, size }` in bytes, or null
+///   without SGX
+/// - `data.memory_encryption`: `{ c_bit, encrypted_guests, min_sev_asid }`,
+///   or null without SME/SEV
+/// - `data.warnings`: list of `{ field, reason }`
+/// - `data.provenance`: map from field name to `{ source, confidence }`
+///
+/// Field names (`CpuField`), sources and confidences use snake_case.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct CpuSnapshot {
+    schema_version: u32,
+    data: CpuData,
+}
+
+impl CpuSnapshot {
+    pub fn new(data: CpuData) -> Self {
+        Self {
+            schema_version: SCHEMA_VERSION,
+            data,
+        }
+    }
+
+    pub fn schema_version(&self) -> u32 {
+        self.schema_version
+    }
+
+    pub fn is_current(&self) -> bool {
+        self.schema_version == SCHEMA_VERSION
+    }
+
+    pub fn data(&self) -> &CpuData {
+        &self.data
+    }
+
+    pub fn into_data(self) -> CpuData {
+        self.data
+    }
+}
@@ -0,0 +1,410 @@
+use std::fmt;
+
+use crate::cpu_info::{CpuData, CpuidDecoder, CpuidSource};
+
+use PageSize::*;
+use TlbType::{Data, Instruction, Unified};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum TlbType {
+    Data,
+    Instruction,
+    Unified,
+    LoadOnly,
+    StoreOnly,
+}
+
+impl fmt::Display for TlbType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            TlbType::Data => "data",
+            TlbType::Instruction => "instruction",
+            TlbType::Unified => "unified",
+            TlbType::LoadOnly => "load-only",
+            TlbType::StoreOnly => "store-only",
+        };
+        write!(f, "{name}")
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum PageSize {
+    #[cfg_attr(feature = "serde", serde(rename = "4k"))]
+    Size4K,
+    #[cfg_attr(feature = "serde", serde(rename = "2m"))]
+    Size2M,
+    #[cfg_attr(feature = "serde", serde(rename = "4m"))]
+    Size4M,
+    #[cfg_attr(feature = "serde", serde(rename = "1g"))]
+    Size1G,
+}
+
+impl PageSize {
+    pub fn bytes(self) -> usize {
+        match self {
+            PageSize::Size4K => 4 << 10,
+            PageSize::Size2M => 2 << 20,
+            PageSize::Size4M => 4 << 20,
+            PageSize::Size1G => 1 << 30,
+        }
+    }
+}
+
+impl fmt::Display for PageSize {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            PageSize::Size4K => "4K",
+            PageSize::Size2M => "2M",
+            PageSize::Size4M => "4M",
+            PageSize::Size1G => "1G",
+        };
+        write!(f, "{name}")
+    }
+}
+
+/// One TLB and the page sizes it translates. Entries are shared by all of
+/// its page sizes.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct TlbDescriptor {
+    pub(crate) level: u8,
+    pub(crate) tlb_type: TlbType,
+    pub(crate) page_sizes: Vec<PageSize>,
+    pub(crate) entries: u32,
+    pub(crate) ways: u32,
+}
+
+impl TlbDescriptor {
+    pub fn level(&self) -> u8 {
+        self.level
+    }
+
+    pub fn tlb_type(&self) -> TlbType {
+        self.tlb_type
+    }
+
+    pub fn page_sizes(&self) -> &[PageSize] {
+        &self.page_sizes
+    }
+
+    pub fn entries(&self) -> u32 {
+        self.entries
+    }
+
+    /// Associativity, 0 for a fully associative TLB.
+    pub fn ways(&self) -> u32 {
+        self.ways
+    }
+}
+
+/// Leaf 0x2 descriptor byte, level, type, page sizes, entries and ways.
+type Leaf2Tlb = (u8, u8, TlbType, &'static [PageSize], u32, u32);
+
+/// TLB descriptors of leaf 0x2, from the Intel SDM. Descriptors without a
+/// documented associativity are left out.
+#[rustfmt::skip]
+const LEAF2_TLBS: &[Leaf2Tlb] = &[
+    (0x01, 1, Instruction, &[Size4K], 32, 4),
+    (0x02, 1, Instruction, &[Size4M], 2, 0),
+    (0x03, 1, Data, &[Size4K], 64, 4),
+    (0x04, 1, Data, &[Size4M], 8, 4),
+    (0x05, 2, Data, &[Size4M], 32, 4),
+    (0x0B, 1, Instruction, &[Size4M], 4, 4),
+    (0x55, 1, Instruction, &[Size2M, Size4M], 7, 0),
+    (0x56, 1, Data, &[Size4M], 16, 4),
+    (0x57, 1, Data, &[Size4K], 16, 4),
+    (0x59, 1, Data, &[Size4K], 16, 0),
+    (0x5A, 1, Data, &[Size2M, Size4M], 32, 4),
+    (0x61, 1, Instruction, &[Size4K], 48, 0),
+    (0x63, 1, Data, &[Size2M, Size4M], 32, 4),
+    (0x63, 1, Data, &[Size1G], 4, 4),
+    (0x64, 1, Data, &[Size4K], 512, 4),
+    (0x6A, 1, Data, &[Size4K], 64, 8),
+    (0x6B, 1, Data, &[Size4K], 256, 8),
+    (0x6C, 1, Data, &[Size2M, Size4M], 128, 8),
+    (0x6D, 1, Data, &[Size1G], 16, 0),
+    (0x76, 1, Instruction, &[Size2M, Size4M], 8, 0),
+    (0xA0, 1, Data, &[Size4K], 32, 0),
+    (0xB0, 1, Instruction, &[Size4K], 128, 4),
+    (0xB1, 1, Instruction, &[Size2M, Size4M], 8, 4),
+    (0xB2, 1, Instruction, &[Size4K], 64, 4),
+    (0xB3, 1, Data, &[Size4K], 128, 4),
+    (0xB4, 2, Data, &[Size4K], 256, 4),
+    (0xB5, 1, Instruction, &[Size4K], 64, 8),
+    (0xB6, 1, Instruction, &[Size4K], 128, 8),
+    (0xBA, 2, Data, &[Size4K], 64, 4),
+    (0xC0, 1, Data, &[Size4K, Size4M], 8, 4),
+    (0xC1, 2, Unified, &[Size4K, Size2M], 1024, 8),
+    (0xC2, 1, Data, &[Size4K, Size2M], 16, 4),
+    (0xC3, 2, Unified, &[Size4K, Size2M], 1536, 6),
+    (0xC3, 2, Unified, &[Size1G], 16, 4),
+    (0xC4, 1, Data, &[Size2M, Size4M], 32, 4),
+    (0xCA, 2, Unified, &[Size4K], 512, 4),
+];
+
+impl<S: CpuidSource + ?Sized> CpuidDecoder<'_, S> {
+    /// TLBs of the CPU executing CPUID and the leaf they were read from:
+    /// Intel's deterministic leaf 0x18, the leaf 0x2 descriptors, or AMD's
+    /// leaves 0x80000005, 0x80000006 and 0x80000019.
+    pub fn tlbs(&self) -> Option<(u32, Vec<TlbDescriptor>)> {
+        [
+            (0x18, self.deterministic_tlbs()),
+            (0x2, self.descriptor_tlbs()),
+            (0x8000_0005, self.amd_tlbs()),
+        ]
+        .into_iter()
+        .find(|(_, tlbs)| !tlbs.is_empty())
+    }
+
+    fn deterministic_tlbs(&self) -> Vec<TlbDescriptor> {
+        let Some(max_subleaf) = self.leaf(0x18, 0).map(|r| r.eax) else {
+            return Vec::new();
+        };
+        let mut tlbs = Vec::new();
+        for sub in 0..=max_subleaf.min(31) {
+            let r = self.leaf(0x18, sub).unwrap_or_default();
+            let tlb_type = match r.edx & 0x1F {
+                1 => TlbType::Data,
+                2 => TlbType::Instruction,
+                3 => TlbType::Unified,
+                4 => TlbType::LoadOnly,
+                5 => TlbType::StoreOnly,
+                _ => continue,
+            };
+            let page_sizes = [Size4K, Size2M, Size4M, Size1G]
+                .into_iter()
+                .enumerate()
+                .filter(|(bit, _)| r.ebx & (1 << bit) != 0)
+                .map(|(_, size)| size)
+                .collect();
+            let ways = r.ebx >> 16;
+            tlbs.push(TlbDescriptor {
+                level: ((r.edx >> 5) & 0x7) as u8,
+                tlb_type,
+                page_sizes,
+                entries: ways.saturating_mul(r.ecx),
+                ways: if r.edx & (1 << 8) != 0 { 0 } else { ways },
+            });
+        }
+        tlbs
+    }
+
+    fn descriptor_tlbs(&self) -> Vec<TlbDescriptor> {
+        let Some(r) = self.leaf(2, 0) else {
+            return Vec::new();
+        };
+        //The low byte of EAX is a repeat count, registers with bit 31 set are reserved
+        let bytes = [r.eax & !0xFF, r.ebx, r.ecx, r.edx]
+            .into_iter()
+            .filter(|reg| reg & (1 << 31) == 0)
+            .flat_map(u32::to_le_bytes)
+            .filter(|&byte| byte != 0);
+
+        let mut tlbs = Vec::new();
+        for byte in bytes {
+            for &(_, level, tlb_type, page_sizes, entries, ways) in
+                LEAF2_TLBS.iter().filter(|tlb| tlb.0 == byte)
+            {
+                tlbs.push(TlbDescriptor {
+                    level,
+                    tlb_type,
+                    page_sizes: page_sizes.to_vec(),
+                    entries,
+                    ways,
+                });
+            }
+        }
+        tlbs.sort_by_key(|t| (t.level, t.tlb_type));
+        tlbs
+    }
+
+    fn amd_tlbs(&self) -> Vec<TlbDescriptor> {
+        let l1 = self.leaf(0x8000_0005, 0).unwrap_or_default();
+        let l2 = self.leaf(0x8000_0006, 0).unwrap_or_default();
+        let huge = self.leaf(0x8000_0019, 0).unwrap_or_default();
+
+        let mut tlbs = Vec::new();
+        let mut push = |level, tlb_type, page_sizes: &[PageSize], ways: Option<u32>, entries| {
+            if let Some(ways) = ways
+                && entries != 0
+            {
+                tlbs.push(TlbDescriptor {
+                    level,
+                    tlb_type,
+                    page_sizes: page_sizes.to_vec(),
+                    entries,
+                    ways,
+                });
+            }
+        };
+        //L1 fields are 8 bits wide: data ways, data entries, instruction ways, instruction entries
+        for (reg, pages) in [(l1.ebx, &[Size4K][..]), (l1.eax, &[Size2M, Size4M])] {
+            let field = |shift: u32| (reg >> shift) & 0xFF;
+            push(1, Data, pages, amd_l1_ways(field(24)), field(16));
+            push(1, Instruction, pages, amd_l1_ways(field(8)), field(0));
+        }
+        //L2 and 1G fields hold a 4 bit associativity code and 12 entry bits
+        for (level, reg, pages) in [
+            (2, l2.ebx, &[Size4K][..]),
+            (2, l2.eax, &[Size2M, Size4M]),
+            (1, huge.eax, &[Size1G]),
+            (2, huge.ebx, &[Size1G]),
+        ] {
+            push(level, Data, pages, amd_ways(reg >> 28), (reg >> 16) & 0xFFF);
+            push(
+                level,
+                Instruction,
+                pages,
+                amd_ways((reg >> 12) & 0xF),
+                reg & 0xFFF,
+            );
+        }
+        tlbs.sort_by_key(|t| (t.level, t.tlb_type));
+        tlbs
+    }
+}
+
+fn amd_l1_ways(code: u32) -> Option<u32> {
+    match code {
+        0 => None,
+        0xFF => Some(0),
+        ways => Some(ways),
+    }
+}
+
+fn amd_ways(code: u32) -> Option<u32> {
+    match code {
+        0x1..=0x4 => Some(code),
+        0x5 => Some(6),
+        0x6 => Some(8),
+        0x8 => Some(16),
+        0xA => Some(32),
+        0xB => Some(48),
+        0xC => Some(64),
+        0xD => Some(96),
+        0xE => Some(128),
+        0xF => Some(0),
+        _ => None,
+    }
+}
+
+impl CpuData {
+    /// Entries of the data-side TLBs of a level that can map `page_size`
+    /// pages. Store-only TLBs are left out, as loads never use them.
+    pub fn tlb_entries(&self, level: u8, page_size: PageSize) -> u32 {
+        self.tlbs
+            .iter()
+            .filter(|t| t.level == level)
+            .filter(|t| matches!(t.tlb_type, Data | Unified | TlbType::LoadOnly))
+            .filter(|t| t.page_sizes.contains(&page_size))
+            .map(|t| t.entries)
+            .sum()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cpu_info::CpuidResult;
+    use crate::cpu_info::x86_cpuid::tests::{intel, table};
+
+    type Tlb = (u8, TlbType, Vec<PageSize>, u32, u32);
+
+    fn tlbs(source: impl Fn(u32, u32) -> CpuidResult) -> Option<(u32, Vec<Tlb>)> {
+        let (leaf, tlbs) = CpuidDecoder::new(&source).tlbs()?;
+        let tlbs = tlbs
+            .into_iter()
+            .map(|t| (t.level, t.tlb_type, t.page_sizes, t.entries, t.ways))
+            .collect();
+        Some((leaf, tlbs))
+    }
+
+    #[test]
+    fn leaf2_descriptors() {
+        //EAX: repeat count 0x01, 0x63 and 0xB5. ECX has bit 31 set and is
+        //skipped. 0x61 is fully associative
+        let source = intel(
+            2,
+            &[(2, 0, [0x00B5_6301, 0x0000_00C3, 0x8000_0001, 0x0000_0061])],
+        );
+        assert_eq!(
+            tlbs(source),
+            Some((
+                0x2,
+                vec![
+                    (1, Data, vec![Size2M, Size4M], 32, 4),
+                    (1, Data, vec![Size1G], 4, 4),
+                    (1, Instruction, vec![Size4K], 64, 8),
+                    (1, Instruction, vec![Size4K], 48, 0),
+                    (2, Unified, vec![Size4K, Size2M], 1536, 6),
+                    (2, Unified, vec![Size1G], 16, 4),
+                ]
+            ))
+        );
+    }
+
+    #[test]
+    fn leaf2_defers_to_leaf_0x18() {
+        //Descriptor 0xFF means the TLBs are only described by leaf 0x18
+        let source = intel(
+            0x18,
+            &[
+                (2, 0, [0x00FF_0001, 0x0000_0003, 0, 0]),
+                (0x18, 0, [1, (8 << 16) | 0b0001, 64, 1 | (1 << 5)]),
+                (
+                    0x18,
+                    1,
+                    [0, (16 << 16) | 0b1110, 1, 2 | (1 << 5) | (1 << 8)],
+                ),
+            ],
+        );
+        assert_eq!(
+            tlbs(source),
+            Some((
+                0x18,
+                vec![
+                    (1, Data, vec![Size4K], 512, 8),
+                    (1, Instruction, vec![Size2M, Size4M, Size1G], 16, 0),
+                ]
+            ))
+        );
+    }
+
+    #[test]
+    fn amd_leaves() {
+        let source = table(&[
+            (0, 0, [1, 0, 0, 0]),
+            (0x8000_0000, 0, [0x8000_0019, 0, 0, 0]),
+            //L1: 0xFF ways is fully associative
+            (0x8000_0005, 0, [0xFF48_FF40, 0xFF48_FF40, 0, 0]),
+            //L2 4K: code 6 is 8 ways. No 2M/4M L2 TLB
+            (0x8000_0006, 0, [0, 0x6C00_6400, 0, 0]),
+            //L1 1G: code 0xF is fully associative
+            (0x8000_0019, 0, [0xF048_F040, 0, 0, 0]),
+        ]);
+        assert_eq!(
+            tlbs(source),
+            Some((
+                0x8000_0005,
+                vec![
+                    (1, Data, vec![Size4K], 72, 0),
+                    (1, Data, vec![Size2M, Size4M], 72, 0),
+                    (1, Data, vec![Size1G], 72, 0),
+                    (1, Instruction, vec![Size4K], 64, 0),
+                    (1, Instruction, vec![Size2M, Size4M], 64, 0),
+                    (1, Instruction, vec![Size1G], 64, 0),
+                    (2, Data, vec![Size4K], 3072, 8),
+                    (2, Instruction, vec![Size4K], 1024, 8),
+                ]
+            ))
+        );
+    }
+
+    #[test]
+    fn no_tlb_leaves() {
+        assert_eq!(tlbs(intel(1, &[])), None);
+    }
+}
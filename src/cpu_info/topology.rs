@@ -0,0 +1,390 @@
+use std::{collections::BTreeMap, fmt};
+
+use crate::cpu_info::{
+    Confidence, CpuData, CpuField, CpuidDecoder, CpuidSource, ProbeOptions, Source,
+};
+
+/// Level of the CPU topology tree, from the outermost.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum TopologyLevel {
+    Package,
+    Die,
+    /// Cores sharing a module, tile or cluster, such as the E-core clusters
+    /// of hybrid Intel CPUs.
+    Module,
+    Core,
+    Thread,
+}
+
+impl TopologyLevel {
+    fn child(self) -> Option<TopologyLevel> {
+        match self {
+            TopologyLevel::Package => Some(TopologyLevel::Die),
+            TopologyLevel::Die => Some(TopologyLevel::Module),
+            TopologyLevel::Module => Some(TopologyLevel::Core),
+            TopologyLevel::Core => Some(TopologyLevel::Thread),
+            TopologyLevel::Thread => None,
+        }
+    }
+}
+
+impl fmt::Display for TopologyLevel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            TopologyLevel::Package => "package",
+            TopologyLevel::Die => "die",
+            TopologyLevel::Module => "module",
+            TopologyLevel::Core => "core",
+            TopologyLevel::Thread => "thread",
+        };
+        write!(f, "{name}")
+    }
+}
+
+/// One package, die, module, core or thread and the logical CPUs below it.
+/// Levels the CPU does not report hold a single node with ID 0.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct TopologyNode {
+    pub(crate) level: TopologyLevel,
+    pub(crate) id: u32,
+    pub(crate) cpus: Vec<usize>,
+    pub(crate) children: Vec<TopologyNode>,
+}
+
+impl TopologyNode {
+    pub fn level(&self) -> TopologyLevel {
+        self.level
+    }
+
+    /// ID within the parent node, or the package ID for packages.
+    pub fn id(&self) -> u32 {
+        self.id
+    }
+
+    /// Logical CPU numbers below this node.
+    pub fn cpus(&self) -> &[usize] {
+        &self.cpus
+    }
+
+    /// Nodes of the next level, ordered by ID. Empty for threads.
+    pub fn children(&self) -> &[TopologyNode] {
+        &self.children
+    }
+
+    /// Number of nodes of `level` below and including this one, e.g. the
+    /// cores of a package.
+    pub fn count(&self, level: TopologyLevel) -> usize {
+        if self.level == level {
+            return 1;
+        }
+        self.children.iter().map(|child| child.count(level)).sum()
+    }
+}
+
+/// IDs of a logical CPU at each level above threads: package, die, module
+/// and core.
+pub(crate) type TopologyIds = [u32; 4];
+
+/// Builds the tree from the IDs of each logical CPU.
+pub(crate) fn topology_tree(cpus: &[(usize, TopologyIds)]) -> Vec<TopologyNode> {
+    let mut members: Vec<(usize, TopologyIds)> = cpus.to_vec();
+    members.sort_by_key(|&(cpu, ids)| (ids, cpu));
+    nodes(TopologyLevel::Package, 0, &members)
+}
+
+fn nodes(level: TopologyLevel, depth: usize, cpus: &[(usize, TopologyIds)]) -> Vec<TopologyNode> {
+    let Some(child) = level.child() else {
+        return cpus
+            .iter()
+            .enumerate()
+            .map(|(thread, &(cpu, _))| TopologyNode {
+                level,
+                id: thread as u32,
+                cpus: vec![cpu],
+                children: Vec::new(),
+            })
+            .collect();
+    };
+
+    let mut groups: BTreeMap<u32, Vec<(usize, TopologyIds)>> = BTreeMap::new();
+    for &(cpu, ids) in cpus {
+        groups.entry(ids[depth]).or_default().push((cpu, ids));
+    }
+    groups
+        .into_iter()
+        .map(|(id, members)| {
+            let mut cpus: Vec<usize> = members.iter().map(|&(cpu, _)| cpu).collect();
+            cpus.sort_unstable();
+            TopologyNode {
+                level,
+                id,
+                cpus,
+                children: nodes(child, depth + 1, &members),
+            }
+        })
+        .collect()
+}
+
+/// Widths of the x2APIC ID fields, as the number of low bits below each
+/// level: threads of a core, cores of a module, modules of a die and dies
+/// of a package. Bits above the last one select the package.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub(crate) struct ApicShifts {
+    smt: u32,
+    core: u32,
+    module: u32,
+    die: u32,
+}
+
+impl ApicShifts {
+    /// Levels that were not reported take no bits.
+    fn normalized(mut self) -> Self {
+        self.core = self.core.max(self.smt);
+        self.module = self.module.max(self.core);
+        self.die = self.die.max(self.module);
+        self
+    }
+
+    pub(crate) fn ids(&self, apic_id: u32) -> TopologyIds {
+        let field = |low: u32, high: u32| {
+            let bits = apic_id.checked_shr(low).unwrap_or(0);
+            bits & 1u32.checked_shl(high - low).map_or(u32::MAX, |v| v - 1)
+        };
+        [
+            apic_id.checked_shr(self.die).unwrap_or(0),
+            field(self.module, self.die),
+            field(self.core, self.module),
+            field(self.smt, self.core),
+        ]
+    }
+}
+
+fn width(count: u32) -> u32 {
+    count.max(1).next_power_of_two().trailing_zeros()
+}
+
+impl<S: CpuidSource + ?Sized> CpuidDecoder<'_, S> {
+    /// APIC ID layout from leaf 0x80000026 on AMD or 0x1F/0xB, else from
+    /// the legacy core and thread counts of leaves 0x1 and 0x4, or
+    /// 0x80000008 on AMD. Returns the leaf it came from.
+    pub(crate) fn apic_shifts(&self) -> (u32, ApicShifts) {
+        let amd = matches!(self.vendor().as_str(), "AuthenticAMD" | "HygonGenuine");
+        let leaves: &[u32] = if amd {
+            &[0x8000_0026, 0x0B]
+        } else {
+            &[0x1F, 0x0B]
+        };
+        for &leaf in leaves {
+            if self.leaf(leaf, 0).is_none_or(|r| r.ebx & 0xFFFF == 0) {
+                continue;
+            }
+            let mut shifts = ApicShifts::default();
+            for subleaf in 0..8 {
+                let r = self.leaf(leaf, subleaf).unwrap_or_default();
+                if r.ebx & 0xFFFF == 0 {
+                    break;
+                }
+                let shift = r.eax & 0x1F;
+                //AMD's level types are core, complex (CCX), die (CCD) and
+                //socket, each giving the bits below the next level
+                match ((r.ecx >> 8) & 0xFF, leaf == 0x8000_0026) {
+                    (1, _) => shifts.smt = shift,
+                    (2, _) => shifts.core = shift,
+                    (3, true) | (3 | 4, false) => shifts.module = shift,
+                    _ => shifts.die = shift,
+                }
+            }
+            let shifts = shifts.normalized();
+            return match leaf {
+                0x0B if amd => (leaf, self.core_complexes(shifts)),
+                _ => (leaf, shifts),
+            };
+        }
+
+        let leaf1 = self.leaf(1, 0).unwrap_or_default();
+        if leaf1.edx & (1 << 28) == 0 {
+            return (1, ApicShifts::default());
+        }
+        let package = width((leaf1.ebx >> 16) & 0xFF);
+
+        if amd && let Some(r) = self.leaf(0x8000_0008, 0) {
+            let core = match (r.ecx >> 12) & 0xF {
+                0 => width((r.ecx & 0xFF) + 1),
+                size => size,
+            };
+            let smt = self
+                .leaf(0x8000_001E, 0)
+                .map_or(0, |r| width(((r.ebx >> 8) & 0xFF) + 1));
+            let shifts = ApicShifts {
+                smt,
+                core,
+                module: core,
+                die: core,
+            };
+            return (0x8000_0008, self.core_complexes(shifts.normalized()));
+        }
+
+        let cores = self.leaf(4, 0).map_or(1, |r| (r.eax >> 26) + 1);
+        let smt = package.saturating_sub(width(cores));
+        let shifts = ApicShifts {
+            smt,
+            core: package,
+            module: package,
+            die: package,
+        };
+        let leaf = if self.max_basic_leaf() >= 4 { 4 } else { 1 };
+        (leaf, shifts.normalized())
+    }
+
+    /// Splits the cores of an AMD package without leaf 0x80000026 into
+    /// CCXs, the cores sharing an L3, and into the nodes of leaf 0x8000001E,
+    /// which are the dies of multi-die CPUs before Zen 2.
+    fn core_complexes(&self, shifts: ApicShifts) -> ApicShifts {
+        let package = shifts.die;
+        let Some(l3) = self
+            .cache_parameters()
+            .into_iter()
+            .find(|c| c.descriptor.level == 3)
+        else {
+            return shifts;
+        };
+        let nodes = self
+            .leaf(0x8000_001E, 0)
+            .map_or(1, |r| ((r.ecx >> 8) & 0x7) + 1);
+        let ccx = l3.sharing_shift.clamp(shifts.smt, package);
+        let die = package.saturating_sub(width(nodes)).max(ccx);
+        ApicShifts {
+            smt: shifts.smt,
+            core: ccx,
+            module: die,
+            die: package,
+        }
+    }
+}
+
+/// Topology tree decoded from the APIC ID of every logical CPU. `apic_ids`
+/// are taken from the OS when it lists them; otherwise CPUID runs on each
+/// CPU. Returns the tree and the leaf that gave the ID layout.
+pub(crate) fn cpuid_topology(
+    options: &ProbeOptions,
+    apic_ids: Option<Vec<(usize, u32)>>,
+) -> Option<(Vec<TopologyNode>, u32)> {
+    let source = options.cpuid_source()?;
+    let (leaf, shifts) = CpuidDecoder::new(&*source).apic_shifts();
+    let apic_ids = apic_ids.unwrap_or_else(|| {
+        options.per_cpu(|cpu, source| (cpu, CpuidDecoder::new(source).apic_id()))
+    });
+    let cpus: Vec<(usize, TopologyIds)> = apic_ids
+        .into_iter()
+        .map(|(cpu, apic_id)| (cpu, shifts.ids(apic_id)))
+        .collect();
+    (!cpus.is_empty()).then(|| (topology_tree(&cpus), leaf))
+}
+
+impl CpuData {
+    /// Replaces core and thread totals with the counts of `tree`.
+    pub(crate) fn set_topology(
+        &mut self,
+        tree: Vec<TopologyNode>,
+        source: Source,
+        confidence: Confidence,
+    ) {
+        self.topology = tree;
+        self.cores = self.topology_count(TopologyLevel::Core);
+        self.threads = self.topology_count(TopologyLevel::Thread);
+        self.record(CpuField::Topology, source, confidence);
+    }
+
+    /// Number of packages (sockets), 0 when the topology tree is unknown.
+    pub fn packages(&self) -> usize {
+        self.topology.len()
+    }
+
+    /// Number of nodes of `level` across all packages.
+    pub fn topology_count(&self, level: TopologyLevel) -> usize {
+        self.topology.iter().map(|node| node.count(level)).sum()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cpu_info::x86_cpuid::tests::{cache_leaf, table};
+
+    /// "AuthenticAMD" source with the given maximum basic and extended leaves.
+    fn amd(
+        max_basic: u32,
+        max_extended: u32,
+        leaves: &[(u32, u32, [u32; 4])],
+    ) -> (u32, ApicShifts) {
+        let mut leaves = leaves.to_vec();
+        leaves.push((0, 0, [max_basic, 0x6874_7541, 0x444D_4163, 0x6974_6E65]));
+        leaves.push((0x8000_0000, 0, [max_extended, 0, 0, 0]));
+        CpuidDecoder::new(&table(&leaves)).apic_shifts()
+    }
+
+    /// Leaf 0x80000026 subleaf of a level type and its shift.
+    fn level(subleaf: u32, level_type: u32, shift: u32) -> (u32, u32, [u32; 4]) {
+        (
+            0x8000_0026,
+            subleaf,
+            [shift, 1 << shift, (level_type << 8) | subleaf, 0],
+        )
+    }
+
+    #[test]
+    fn amd_level_types() {
+        //Core, complex, die and socket give the bits below the next level
+        let (leaf, shifts) = amd(
+            1,
+            0x8000_0026,
+            &[
+                level(0, 1, 1),
+                level(1, 2, 3),
+                level(2, 3, 4),
+                level(3, 4, 5),
+            ],
+        );
+        assert_eq!(leaf, 0x8000_0026);
+        assert_eq!(
+            shifts,
+            ApicShifts {
+                smt: 1,
+                core: 3,
+                module: 4,
+                die: 5,
+            }
+        );
+        //Package 1, die 0, CCX 1, core 2 of the CCX, thread 1
+        assert_eq!(shifts.ids(0b10_1101), [1, 0, 1, 2]);
+    }
+
+    #[test]
+    fn amd_legacy_leaves() {
+        //No leaf 0x80000026 or 0xB: 32 threads in a 5 bit APIC ID, two
+        //threads per core from 0x8000001E and an L3 shared by 16 threads
+        let (leaf, shifts) = amd(
+            1,
+            0x8000_001E,
+            &[
+                (1, 0, [0, 32 << 16, 0, 1 << 28]),
+                (0x8000_0008, 0, [0, 0, (5 << 12) | 31, 0]),
+                (0x8000_001D, 0, cache_leaf(3, 3, 16, 16, 64, 32768)),
+                (0x8000_001E, 0, [0, 1 << 8, 0, 0]),
+            ],
+        );
+        assert_eq!(leaf, 0x8000_0008);
+        assert_eq!(
+            shifts,
+            ApicShifts {
+                smt: 1,
+                core: 4,
+                module: 5,
+                die: 5,
+            }
+        );
+    }
+}
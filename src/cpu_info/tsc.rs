@@ -0,0 +1,135 @@
+use crate::cpu_info::{Confidence, CpuidDecoder, CpuidSource, Provenance, Source};
+
+/// Crystal clock in Hz of Intel CPUs whose leaf 0x15 leaves it out, by
+/// family 6 model.
+const CRYSTAL_HZ: [(u8, u64); 7] = [
+    (0x4E, 24_000_000),
+    (0x5E, 24_000_000),
+    (0x8E, 24_000_000),
+    (0x9E, 24_000_000),
+    (0x55, 25_000_000),
+    (0x5F, 25_000_000),
+    (0x5C, 19_200_000),
+];
+
+impl<S: CpuidSource + ?Sized> CpuidDecoder<'_, S> {
+    /// TSC frequency in Hz from the crystal ratio of leaf 0x15, or from the
+    /// timing leaf 0x40000010 some hypervisors provide.
+    pub fn tsc_frequency(&self) -> Option<(u64, Provenance)> {
+        if let Some(r) = self.leaf(0x15, 0)
+            && r.eax != 0
+            && r.ebx != 0
+        {
+            let ratio = |crystal: u64| crystal * r.ebx as u64 / r.eax as u64;
+            if r.ecx != 0 {
+                let provenance = Provenance::new(Source::Cpuid { leaf: 0x15 }, Confidence::High);
+                return Some((ratio(r.ecx as u64), provenance));
+            }
+            //Without the crystal, use the known one of the model or the base
+            //clock, which the TSC ticks at on these CPUs
+            let signature = self.signature();
+            let crystal = CRYSTAL_HZ
+                .iter()
+                .find(|&&(model, _)| signature.family == 6 && model == signature.model);
+            let hz = match crystal {
+                Some(&(_, crystal)) => ratio(crystal),
+                None => self.frequencies().0 as u64 * 1_000_000,
+            };
+            if hz != 0 {
+                let provenance = Provenance::new(Source::Cpuid { leaf: 0x15 }, Confidence::Medium);
+                return Some((hz, provenance));
+            }
+        }
+
+        let khz = self.leaf(0x4000_0010, 0)?.eax;
+        (khz != 0).then(|| {
+            let provenance = Provenance::new(Source::Cpuid { leaf: 0x4000_0010 }, Confidence::High);
+            (khz as u64 * 1000, provenance)
+        })
+    }
+}
+
+/// Measures the TSC frequency in Hz against the OS's monotonic clock over a
+/// few milliseconds. `reference` names the clock and returns nanoseconds.
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+pub(crate) fn calibrate_tsc(reference: (&str, fn() -> u64)) -> Option<(u64, Source)> {
+    #[cfg(target_arch = "x86")]
+    use std::arch::x86::_rdtsc;
+    #[cfg(target_arch = "x86_64")]
+    use std::arch::x86_64::_rdtsc;
+
+    const WINDOW_NS: u64 = 20_000_000;
+    let (name, now) = reference;
+    let start = now();
+    let start_tsc = unsafe { _rdtsc() };
+    let mut end = start;
+    while end - start < WINDOW_NS {
+        end = now();
+    }
+    let end_tsc = unsafe { _rdtsc() };
+
+    let ticks = end_tsc.checked_sub(start_tsc)? as u128;
+    let hz = ticks * 1_000_000_000 / (end - start) as u128;
+    //Rounded to kHz, the precision the kernel keeps as well
+    let hz = (hz + 500) / 1000 * 1000;
+    Some((hz as u64, Source::Calibration(name.into())))
+}
+
+#[cfg(not(any(target_arch = "x86", target_arch = "x86_64")))]
+pub(crate) fn calibrate_tsc(_reference: (&str, fn() -> u64)) -> Option<(u64, Source)> {
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cpu_info::CpuidResult;
+    use crate::cpu_info::x86_cpuid::tests::{hypervisor_leaf, intel};
+
+    fn tsc(source: impl Fn(u32, u32) -> CpuidResult) -> Option<(u64, Confidence)> {
+        CpuidDecoder::new(&source)
+            .tsc_frequency()
+            .map(|(hz, provenance)| (hz, provenance.confidence()))
+    }
+
+    #[test]
+    fn crystal_ratio() {
+        let source = intel(0x15, &[(0x15, 0, [2, 176, 24_000_000, 0])]);
+        assert_eq!(tsc(source), Some((2_112_000_000, Confidence::High)));
+    }
+
+    #[test]
+    fn crystal_of_the_model() {
+        //Coffee Lake leaves the crystal out of leaf 0x15
+        let source = intel(
+            0x16,
+            &[(1, 0, [0x0009_06EA, 0, 0, 0]), (0x15, 0, [2, 300, 0, 0])],
+        );
+        assert_eq!(tsc(source), Some((3_600_000_000, Confidence::Medium)));
+
+        //Unlisted models fall back to the base clock of leaf 0x16
+        let source = intel(
+            0x16,
+            &[
+                (1, 0, [0x000A_06A4, 0, 0, 0]),
+                (0x15, 0, [2, 300, 0, 0]),
+                (0x16, 0, [2100, 4500, 100, 0]),
+            ],
+        );
+        assert_eq!(tsc(source), Some((2_100_000_000, Confidence::Medium)));
+    }
+
+    #[test]
+    fn hypervisor_timing_leaf() {
+        let source = intel(
+            1,
+            &[
+                (1, 0, [0, 0, 1 << 31, 0]),
+                hypervisor_leaf(0x4000_0000, 0x4000_0010, b"VMwareVMware"),
+                (0x4000_0010, 0, [2_000_000, 0, 0, 0]),
+            ],
+        );
+        assert_eq!(tsc(source), Some((2_000_000_000, Confidence::High)));
+        assert_eq!(tsc(intel(1, &[])), None);
+    }
+}
@@ -1,132 +1,182 @@
 use windows::{
     Win32::System::{
+        Performance::{QueryPerformanceCounter, QueryPerformanceFrequency},
         Registry::{
             HKEY, HKEY_LOCAL_MACHINE, KEY_READ, REG_VALUE_TYPE, RegOpenKeyExW, RegQueryValueExW,
         },
         SystemInformation::{
-            GetLogicalProcessorInformationEx, GetTickCount64, LOGICAL_PROCESSOR_RELATIONSHIP,
-            RelationProcessorCore, SYSTEM_LOGICAL_PROCESSOR_INFORMATION_EX,
+            CacheData, CacheInstruction, CacheUnified, GetLogicalProcessorInformationEx,
+            GetTickCount64, LOGICAL_PROCESSOR_RELATIONSHIP, RelationCache, RelationProcessorCore,
+            SYSTEM_LOGICAL_PROCESSOR_INFORMATION_EX,
         },
     },
     core::PCWSTR,
 };
 
-use crate::cpu_info::{CpuData, CpuInfo};
+use crate::cpu_info::{
+    CacheDescriptor, CacheType, Confidence, CpuData, CpuFeature, CpuField, CpuInfo, CpuidDecoder,
+    ProbeError, ProbeOptions, Source, TopologyLevel, cache::cpuid_caches,
+    core_types::cpuid_core_groups, logical_cpus::cpuid_logical_cpus, topology::cpuid_topology,
+    tsc::calibrate_tsc, x86_cpuid::decode_cpu_data,
+};
 
 pub struct WindowsCpuInfo(CpuData);
 impl CpuInfo for WindowsCpuInfo {
-    fn new() -> Self {
-        let mut results = CpuData::default();
-        results.arch = std::env::consts::ARCH.to_string();
-
-        //Vendor
-        let (_, b, c, d) = cpuid(0, 0);
-        let mut vendor = Vec::new();
-        vendor.extend_from_slice(&b.to_le_bytes());
-        vendor.extend_from_slice(&d.to_le_bytes());
-        vendor.extend_from_slice(&c.to_le_bytes());
-        results.vendor = String::from_utf8_lossy(&vendor).trim().to_string();
-
-        //Brand
-        let max_ext = cpuid(0x80000000, 0).0;
-        if max_ext >= 0x80000004 {
-            for leaf in 0x80000002..=0x80000004 {
-                let (ea, eb, ec, ed) = cpuid(leaf, 0);
-                for r in [ea, eb, ec, ed] {
-                    results
-                        .brand
-                        .push_str(&String::from_utf8_lossy(&r.to_le_bytes()));
-                }
-            }
-            results.brand = results.brand.trim_matches(char::from(0)).trim().to_string();
-        }
-
-        let (eax, _, ecx, edx) = cpuid(1, 0);
+    /// The filesystem root of `options` does not apply on Windows.
+    fn with_options(options: &ProbeOptions) -> Result<Self, ProbeError> {
+        let source = options.cpuid_source().ok_or(ProbeError::CpuidUnavailable)?;
+        let mut results = decode_cpu_data(&*source, options)?;
 
-        //Family/Model/Stepping
-        results.stepping = (eax & 0xF) as u8;
-        let model = ((eax >> 4) & 0xF) as u8;
-        let family = ((eax >> 8) & 0xF) as u8;
-        let ext_model = ((eax >> 16) & 0xF) as u8;
-        let ext_family = ((eax >> 20) & 0xFF) as u8;
-        results.family = if family == 0xF {
-            family + ext_family
-        } else {
-            family
-        };
-        results.model = if family == 0x6 || family == 0xF {
-            (ext_model << 4) + model
+        //Arch
+        let wants_topology = options.wants(CpuField::Topology) || options.wants(CpuField::Caches);
+        let os_topology = if wants_topology && options.probes_host() {
+            topology()
         } else {
-            model
+            None
         };
-
-        //Flags
-        if (ecx & (1 << 23)) != 0 {
-            results.flags.push("POP_CNT".into());
-        }
-        if (edx & (1 << 25)) != 0 {
-            results.flags.push("SSE".into());
-        }
-        if (edx & (1 << 26)) != 0 {
-            results.flags.push("SSE2".into());
-        }
-        if (ecx & (1 << 0)) != 0 {
-            results.flags.push("SSE3".into());
-        }
-        if (ecx & (1 << 9)) != 0 {
-            results.flags.push("SSSE3".into());
-        }
-        if (ecx & (1 << 19)) != 0 {
-            results.flags.push("SSE4.1".into());
-        }
-        if (ecx & (1 << 20)) != 0 {
-            results.flags.push("SSE4.2".into());
-        }
-        if (ecx & (1 << 25)) != 0 {
-            results.flags.push("AES".into());
-        }
-        if (ecx & (1 << 28)) != 0 {
-            results.flags.push("AVX".into());
+        if let Some((cores, threads)) = os_topology {
+            results.cores = cores;
+            results.threads = threads;
+            results.record(
+                CpuField::Topology,
+                Source::OsApi("GetLogicalProcessorInformationEx".into()),
+                Confidence::High,
+            );
+            if options.wants(CpuField::Caches) {
+                results.set_caches(&CpuidDecoder::new(&*source));
+            }
         }
 
-        let (_, ebx, _, _) = cpuid(7, 0);
-        if (ebx & (1 << 5)) != 0 {
-            results.flags.push("AVX2".into());
-        }
-        if (ebx & (1 << 8)) != 0 {
-            results.flags.push("BMI2".into());
+        //Topology tree, decoded from every CPU's APIC ID when it agrees with
+        //the totals Windows reports
+        if options.wants(CpuField::Topology)
+            && let Some((tree, leaf)) = cpuid_topology(options, None)
+        {
+            let count = |level| tree.iter().map(|p| p.count(level)).sum::<usize>();
+            let totals = (count(TopologyLevel::Core), count(TopologyLevel::Thread));
+            if os_topology.is_none_or(|os| os == totals) {
+                results.set_topology(tree, Source::Cpuid { leaf }, Confidence::High);
+            }
         }
-        if (ebx & (1 << 16)) != 0 {
-            results.flags.push("AVX512F".into());
+
+        //Caches, Windows knows which processors share each instance. A
+        //replayed dump needs CPUID on every CPU of it instead
+        if options.wants(CpuField::Caches) {
+            let caches = if options.probes_host() {
+                caches()
+            } else {
+                Vec::new()
+            };
+            if !caches.is_empty() {
+                results.set_os_caches(
+                    caches,
+                    Source::OsApi("GetLogicalProcessorInformationEx".into()),
+                );
+            } else {
+                results.set_cpuid_caches(cpuid_caches(options));
+            }
         }
-        if (ebx & (1 << 30)) != 0 {
-            results.flags.push("AVX512BW".into());
+
+        //Hybrid core types
+        if options.wants(CpuField::CoreTypes) {
+            let (groups, caches) = cpuid_core_groups(options);
+            if !groups.is_empty() {
+                results.set_cpuid_caches(caches);
+                results.set_core_groups(groups);
+                results.record(
+                    CpuField::CoreTypes,
+                    Source::Cpuid { leaf: 0x1A },
+                    Confidence::High,
+                );
+            } else if results.has(CpuFeature::HybridCpu) {
+                results.warn(CpuField::CoreTypes, "no CPUID leaf 0x1A core types");
+            }
         }
-        if (ebx & (1 << 31)) != 0 {
-            results.flags.push("AVX512V1".into());
+
+        //Clock, the registry describes the host rather than a replayed dump
+        if options.wants(CpuField::ClockSpeed) && results.clock_speed == 0 {
+            if options.probes_host() {
+                match read_registry_mhz() {
+                    Some(base_clock) => {
+                        results.clock_speed = base_clock;
+                        results.record(
+                            CpuField::ClockSpeed,
+                            Source::Registry("~MHz".into()),
+                            Confidence::Medium,
+                        );
+                    }
+                    None => results.warn(
+                        CpuField::ClockSpeed,
+                        "CPUID leaf 0x16 unsupported and registry ~MHz unreadable",
+                    ),
+                }
+            } else {
+                results.warn(
+                    CpuField::ClockSpeed,
+                    "CPUID leaf 0x16 unsupported and registry not available for a replayed dump",
+                );
+            }
         }
 
-        //Arch
-        (
-            results.cores,
-            results.threads,
-            results.l1_cache,
-            results.l2_cache,
-            results.l3_cache,
-        ) = topo_and_caches();
+        //Per-CPU table, the registry has each processor's microcode
+        if options.wants(CpuField::LogicalCpus) {
+            let mut cpus = cpuid_logical_cpus(options);
+            if options.probes_host() {
+                for cpu in &mut cpus {
+                    if let Some(revision) = read_microcode(cpu.cpu) {
+                        cpu.microcode = revision;
+                    }
+                }
+            }
+            results.set_logical_cpus(cpus);
+        }
 
-        //Clock
-        (results.clock_speed, results.clock_speed_turbo, _, _) = cpuid(0x16, 0);
-        if results.clock_speed == 0
-            && let Some(base_clock) = read_registry_mhz()
-        {
-            results.clock_speed = base_clock
+        //TSC
+        if options.wants(CpuField::TscFrequency) && results.tsc_frequency == 0 {
+            let calibrated = options
+                .calibrates_tsc()
+                .then(|| calibrate_tsc(("QueryPerformanceCounter", performance_counter_ns)))
+                .flatten();
+            match calibrated {
+                Some((hz, source)) => {
+                    results.tsc_frequency = hz;
+                    results.record(CpuField::TscFrequency, source, Confidence::Low);
+                }
+                None if !options.calibrates_tsc() => results.warn(
+                    CpuField::TscFrequency,
+                    "no CPUID TSC ratio, and calibration not requested",
+                ),
+                None => results.warn(CpuField::TscFrequency, "no CPUID TSC ratio"),
+            }
         }
 
         //Microcode
-        results.microcode = read_microcode().unwrap_or_default();
+        if options.wants(CpuField::Microcode) {
+            if options.probes_host() {
+                match read_microcode(0) {
+                    Some(microcode) => {
+                        results.microcode = microcode;
+                        results.record(
+                            CpuField::Microcode,
+                            Source::Registry("Update Revision".into()),
+                            Confidence::High,
+                        );
+                    }
+                    None => results.warn(
+                        CpuField::Microcode,
+                        "registry value \"Update Revision\" unreadable",
+                    ),
+                }
+            } else {
+                results.warn(
+                    CpuField::Microcode,
+                    "registry not available for a replayed dump",
+                );
+            }
+        }
+        results.skip_unrequested(options);
 
-        Self(results)
+        Ok(Self(results))
     }
 
     fn static_data(&self) -> &CpuData {
@@ -138,38 +188,30 @@ impl CpuInfo for WindowsCpuInfo {
     }
 }
 
-#[inline]
-fn cpuid(eax: u32, ecx: u32) -> (u32, u32, u32, u32) {
-    if cfg!(target_arch = "x86_64") {
-        unsafe {
-            let r = core::arch::x86_64::__cpuid_count(eax, ecx);
-            (r.eax, r.ebx, r.ecx, r.edx)
-        }
-    } else {
-        (0, 0, 0, 0)
-    }
-}
-
-fn topo_and_caches() -> (usize, usize, usize, usize, usize) {
+/// Raw `GetLogicalProcessorInformationEx` records of one relationship.
+fn processor_information(relationship: LOGICAL_PROCESSOR_RELATIONSHIP) -> Option<Vec<u8>> {
     let mut needed: u32 = 0;
-    _ = unsafe {
-        GetLogicalProcessorInformationEx(LOGICAL_PROCESSOR_RELATIONSHIP(0), None, &mut needed)
-    };
+    _ = unsafe { GetLogicalProcessorInformationEx(relationship, None, &mut needed) };
     let mut buf = vec![0u8; needed as usize];
 
     let ok = unsafe {
         GetLogicalProcessorInformationEx(
-            LOGICAL_PROCESSOR_RELATIONSHIP(0),
+            relationship,
             Some(buf.as_mut_ptr() as *mut _),
             &mut needed,
         )
     };
     if ok.is_err() {
-        return (0, 0, 0, 0, 0);
+        return None;
     }
+    buf.truncate(needed as usize);
+    Some(buf)
+}
 
+fn topology() -> Option<(usize, usize)> {
+    let buf = processor_information(RelationProcessorCore)?;
     let mut p = buf.as_ptr();
-    let end = unsafe { p.add(needed as usize) };
+    let end = unsafe { p.add(buf.len()) };
 
     let mut cores = 0;
     let mut threads = 0;
@@ -189,15 +231,70 @@ fn topo_and_caches() -> (usize, usize, usize, usize, usize) {
         p = unsafe { p.add(size) };
     }
 
-    let (l1, l2, l3) = cache_size(cores, threads);
+    (cores != 0).then_some((cores, threads))
+}
+
+/// Cache instances and the logical processors sharing them. Windows does not
+/// report partitions or inclusivity.
+fn caches() -> Vec<CacheDescriptor> {
+    let Some(buf) = processor_information(RelationCache) else {
+        return Vec::new();
+    };
+    let mut p = buf.as_ptr();
+    let end = unsafe { p.add(buf.len()) };
+
+    let mut caches = Vec::new();
+    while p < end {
+        let info = unsafe { &*(p as *const SYSTEM_LOGICAL_PROCESSOR_INFORMATION_EX) };
+        let size = info.Size as usize;
+
+        if info.Relationship == RelationCache {
+            let cache = unsafe { &info.Anonymous.Cache };
+            let cache_type = match cache.Type {
+                t if t == CacheData => Some(CacheType::Data),
+                t if t == CacheInstruction => Some(CacheType::Instruction),
+                t if t == CacheUnified => Some(CacheType::Unified),
+                _ => None,
+            };
+            if let Some(cache_type) = cache_type {
+                let mask = unsafe { cache.Anonymous.GroupMask };
+                let ways = match cache.Associativity {
+                    0xFF => 0,
+                    ways => ways as u32,
+                };
+                let line_size = cache.LineSize as u32;
+                caches.push(CacheDescriptor {
+                    level: cache.Level,
+                    cache_type,
+                    size: cache.CacheSize as usize,
+                    ways,
+                    line_size,
+                    sets: cache.CacheSize / (ways.max(1) * line_size.max(1)),
+                    partitions: 1,
+                    inclusive: None,
+                    shared_cpus: (0..usize::BITS as usize)
+                        .filter(|bit| mask.Mask & (1 << bit) != 0)
+                        .map(|bit| mask.Group as usize * usize::BITS as usize + bit)
+                        .collect(),
+                });
+            }
+        }
+        p = unsafe { p.add(size) };
+    }
+    caches.sort_by(|a, b| {
+        (a.level, a.cache_type, &a.shared_cpus).cmp(&(b.level, b.cache_type, &b.shared_cpus))
+    });
+    caches
+}
 
-    (
-        cores,
-        threads,
-        l1,
-        l2,
-        l3,
-    )
+fn performance_counter_ns() -> u64 {
+    let mut counter = 0;
+    let mut frequency = 0;
+    unsafe {
+        _ = QueryPerformanceCounter(&mut counter);
+        _ = QueryPerformanceFrequency(&mut frequency);
+    }
+    (counter as u128 * 1_000_000_000 / frequency.max(1) as u128) as u64
 }
 
 fn read_registry_mhz() -> Option<u32> {
@@ -235,11 +332,13 @@ fn read_registry_mhz() -> Option<u32> {
     }
 }
 
-fn read_microcode() -> Option<String> {
+fn read_microcode(cpu: usize) -> Option<String> {
     use windows::Win32::System::Registry::*;
     unsafe {
         let mut h: HKEY = HKEY::default();
-        let path = to_wstring("HARDWARE\\DESCRIPTION\\System\\CentralProcessor\\0");
+        let path = to_wstring(&format!(
+            "HARDWARE\\DESCRIPTION\\System\\CentralProcessor\\{cpu}"
+        ));
         if RegOpenKeyExW(
             HKEY_LOCAL_MACHINE,
             PCWSTR(path.as_ptr()),
@@ -279,49 +378,3 @@ fn to_wstring(s: &str) -> Vec<u16> {
         .chain(std::iter::once(0))
         .collect()
 }
-
-pub fn cache_size(cores: usize, threads: usize) -> (usize, usize, usize) {
-    let max_ext = cpuid(0x8000_0000, 0).0;
-    if max_ext >= 0x8000_001D {
-        cache_size_universal(0x8000_001D, cores, threads)
-    } else {
-        cache_size_universal(4, cores, 0)
-    }
-}
-
-fn cache_size_universal(func: u32, cores: usize, threads_shared: usize) -> (usize, usize, usize) {
-    let mut cache = [0, 0, 0];
-    for i in 0.. {
-        let (eax, ebx, ecx, _) = cpuid(func, i);
-        let cache_type = eax & 0x1F;
-        if cache_type == 0 {
-            break;
-        }
-
-        let level = (eax >> 5) & 0x7;
-        let line_size = (ebx & 0xFFF) + 1;
-        let partitions = ((ebx >> 12) & 0x3FF) + 1;
-        let ways = ((ebx >> 22) & 0x3FF) + 1;
-        let sets = ecx + 1;
-        let size = ways * partitions * line_size * sets;
-
-        let shared_logical = if threads_shared > 0 {
-            threads_shared as u32 / (((eax >> 14) & 0xFFF) + 1)
-        } else {
-            1
-        };
-
-        match (cache_type, level) {
-            (1, 1) => cache[0] += size,
-            (2, 1) => cache[0] += size,
-            (3, 2) => cache[1] += size,
-            (3, 3) => cache[2] += size * shared_logical,
-            _ => {}
-        }
-    }
-    (
-        cache[0] as usize * cores,
-        cache[1] as usize * cores,
-        cache[2] as usize,
-    )
-}
\ No newline at end of file
@@ -0,0 +1,1332 @@
+use crate::cpu_info::{
+    CacheDescriptor, CacheType, Confidence, CoreType, CpuData, CpuFeature, CpuFeatures, CpuField,
+    ProbeError, ProbeOptions, Provenance, Source,
+    cache::{CacheParameters, cache_instances},
+};
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct CpuidResult {
+    pub eax: u32,
+    pub ebx: u32,
+    pub ecx: u32,
+    pub edx: u32,
+}
+
+impl CpuidResult {
+    pub fn new(eax: u32, ebx: u32, ecx: u32, edx: u32) -> Self {
+        Self { eax, ebx, ecx, edx }
+    }
+
+    pub fn is_zero(&self) -> bool {
+        self.eax == 0 && self.ebx == 0 && self.ecx == 0 && self.edx == 0
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Register {
+    Eax,
+    Ebx,
+    Ecx,
+    Edx,
+}
+
+impl CpuidResult {
+    fn register(&self, register: Register) -> u32 {
+        match register {
+            Register::Eax => self.eax,
+            Register::Ebx => self.ebx,
+            Register::Ecx => self.ecx,
+            Register::Edx => self.edx,
+        }
+    }
+}
+
+/// One register of a feature leaf: `(leaf, subleaf, register, [(bit, feature)])`.
+type FeatureWord = (u32, u32, Register, &'static [(u32, CpuFeature)]);
+
+const FEATURE_BITS: &[FeatureWord] = &[
+    (
+        0x1,
+        0,
+        Register::Edx,
+        &[
+            (0, CpuFeature::Fpu),
+            (1, CpuFeature::Vme),
+            (2, CpuFeature::De),
+            (3, CpuFeature::Pse),
+            (4, CpuFeature::Tsc),
+            (5, CpuFeature::Msr),
+            (6, CpuFeature::Pae),
+            (7, CpuFeature::Mce),
+            (8, CpuFeature::Cx8),
+            (9, CpuFeature::Apic),
+            (11, CpuFeature::Sep),
+            (12, CpuFeature::Mtrr),
+            (13, CpuFeature::Pge),
+            (14, CpuFeature::Mca),
+            (15, CpuFeature::Cmov),
+            (16, CpuFeature::Pat),
+            (17, CpuFeature::Pse36),
+            (18, CpuFeature::Pn),
+            (19, CpuFeature::Clflush),
+            (21, CpuFeature::Dts),
+            (22, CpuFeature::Acpi),
+            (23, CpuFeature::Mmx),
+            (24, CpuFeature::Fxsr),
+            (25, CpuFeature::Sse),
+            (26, CpuFeature::Sse2),
+            (27, CpuFeature::Ss),
+            (28, CpuFeature::Ht),
+            (29, CpuFeature::Tm),
+            (30, CpuFeature::Ia64),
+            (31, CpuFeature::Pbe),
+        ],
+    ),
+    (
+        0x1,
+        0,
+        Register::Ecx,
+        &[
+            (0, CpuFeature::Sse3),
+            (1, CpuFeature::Pclmulqdq),
+            (2, CpuFeature::Dtes64),
+            (3, CpuFeature::Monitor),
+            (4, CpuFeature::DsCpl),
+            (5, CpuFeature::Vmx),
+            (6, CpuFeature::Smx),
+            (7, CpuFeature::Est),
+            (8, CpuFeature::Tm2),
+            (9, CpuFeature::Ssse3),
+            (10, CpuFeature::Cid),
+            (11, CpuFeature::Sdbg),
+            (12, CpuFeature::Fma),
+            (13, CpuFeature::Cx16),
+            (14, CpuFeature::Xtpr),
+            (15, CpuFeature::Pdcm),
+            (17, CpuFeature::Pcid),
+            (18, CpuFeature::Dca),
+            (19, CpuFeature::Sse4_1),
+            (20, CpuFeature::Sse4_2),
+            (21, CpuFeature::X2apic),
+            (22, CpuFeature::Movbe),
+            (23, CpuFeature::Popcnt),
+            (24, CpuFeature::TscDeadlineTimer),
+            (25, CpuFeature::Aes),
+            (26, CpuFeature::Xsave),
+            (27, CpuFeature::Osxsave),
+            (28, CpuFeature::Avx),
+            (29, CpuFeature::F16c),
+            (30, CpuFeature::Rdrand),
+            (31, CpuFeature::Hypervisor),
+        ],
+    ),
+    (
+        0x8000_0001,
+        0,
+        Register::Edx,
+        &[
+            (11, CpuFeature::Syscall),
+            (19, CpuFeature::Mp),
+            (20, CpuFeature::Nx),
+            (22, CpuFeature::Mmxext),
+            (25, CpuFeature::FxsrOpt),
+            (26, CpuFeature::Pdpe1gb),
+            (27, CpuFeature::Rdtscp),
+            (29, CpuFeature::Lm),
+            (30, CpuFeature::ThreeDNowExt),
+            (31, CpuFeature::ThreeDNow),
+        ],
+    ),
+    (
+        0x8000_0001,
+        0,
+        Register::Ecx,
+        &[
+            (0, CpuFeature::LahfLm),
+            (1, CpuFeature::CmpLegacy),
+            (2, CpuFeature::Svm),
+            (3, CpuFeature::Extapic),
+            (4, CpuFeature::Cr8Legacy),
+            (5, CpuFeature::Abm),
+            (6, CpuFeature::Sse4a),
+            (7, CpuFeature::Misalignsse),
+            (8, CpuFeature::ThreeDNowPrefetch),
+            (9, CpuFeature::Osvw),
+            (10, CpuFeature::Ibs),
+            (11, CpuFeature::Xop),
+            (12, CpuFeature::Skinit),
+            (13, CpuFeature::Wdt),
+            (15, CpuFeature::Lwp),
+            (16, CpuFeature::Fma4),
+            (17, CpuFeature::Tce),
+            (19, CpuFeature::NodeidMsr),
+            (21, CpuFeature::Tbm),
+            (22, CpuFeature::Topoext),
+            (23, CpuFeature::PerfctrCore),
+            (24, CpuFeature::PerfctrNb),
+            (26, CpuFeature::Bpext),
+            (27, CpuFeature::Ptsc),
+            (28, CpuFeature::PerfctrLlc),
+            (29, CpuFeature::Mwaitx),
+        ],
+    ),
+    (
+        0x8000_0007,
+        0,
+        Register::Edx,
+        &[
+            (0, CpuFeature::Ts),
+            (1, CpuFeature::Fid),
+            (2, CpuFeature::Vid),
+            (3, CpuFeature::Ttp),
+            (4, CpuFeature::Htc),
+            (5, CpuFeature::Stc),
+            (6, CpuFeature::Steps100Mhz),
+            (7, CpuFeature::HwPstate),
+            (8, CpuFeature::ConstantTsc),
+            (9, CpuFeature::Cpb),
+            (10, CpuFeature::EffFreqRo),
+            (11, CpuFeature::ProcFeedback),
+            (12, CpuFeature::AccPower),
+        ],
+    ),
+    (
+        0x6,
+        0,
+        Register::Eax,
+        &[
+            (0, CpuFeature::Dtherm),
+            (1, CpuFeature::Ida),
+            (2, CpuFeature::Arat),
+            (4, CpuFeature::Pln),
+            (6, CpuFeature::Pts),
+            (7, CpuFeature::Hwp),
+            (8, CpuFeature::HwpNotify),
+            (9, CpuFeature::HwpActWindow),
+            (10, CpuFeature::HwpEpp),
+            (11, CpuFeature::HwpPkgReq),
+            (15, CpuFeature::HwpHighestPerfChange),
+            (19, CpuFeature::Hfi),
+        ],
+    ),
+    (
+        0x6,
+        0,
+        Register::Ecx,
+        &[(0, CpuFeature::Aperfmperf), (3, CpuFeature::Epb)],
+    ),
+    (
+        0x7,
+        0,
+        Register::Ebx,
+        &[
+            (0, CpuFeature::Fsgsbase),
+            (1, CpuFeature::TscAdjust),
+            (2, CpuFeature::Sgx),
+            (3, CpuFeature::Bmi1),
+            (4, CpuFeature::Hle),
+            (5, CpuFeature::Avx2),
+            (6, CpuFeature::FdpExcptnOnly),
+            (7, CpuFeature::Smep),
+            (8, CpuFeature::Bmi2),
+            (9, CpuFeature::Erms),
+            (10, CpuFeature::Invpcid),
+            (11, CpuFeature::Rtm),
+            (12, CpuFeature::Cqm),
+            (13, CpuFeature::ZeroFcsFds),
+            (14, CpuFeature::Mpx),
+            (15, CpuFeature::RdtA),
+            (16, CpuFeature::Avx512f),
+            (17, CpuFeature::Avx512dq),
+            (18, CpuFeature::Rdseed),
+            (19, CpuFeature::Adx),
+            (20, CpuFeature::Smap),
+            (21, CpuFeature::Avx512ifma),
+            (23, CpuFeature::Clflushopt),
+            (24, CpuFeature::Clwb),
+            (25, CpuFeature::IntelPt),
+            (26, CpuFeature::Avx512pf),
+            (27, CpuFeature::Avx512er),
+            (28, CpuFeature::Avx512cd),
+            (29, CpuFeature::ShaNi),
+            (30, CpuFeature::Avx512bw),
+            (31, CpuFeature::Avx512vl),
+        ],
+    ),
+    (
+        0x7,
+        0,
+        Register::Ecx,
+        &[
+            (0, CpuFeature::Prefetchwt1),
+            (1, CpuFeature::Avx512vbmi),
+            (2, CpuFeature::Umip),
+            (3, CpuFeature::Pku),
+            (4, CpuFeature::Ospke),
+            (5, CpuFeature::Waitpkg),
+            (6, CpuFeature::Avx512Vbmi2),
+            (7, CpuFeature::Shstk),
+            (8, CpuFeature::Gfni),
+            (9, CpuFeature::Vaes),
+            (10, CpuFeature::Vpclmulqdq),
+            (11, CpuFeature::Avx512Vnni),
+            (12, CpuFeature::Avx512Bitalg),
+            (13, CpuFeature::Tme),
+            (14, CpuFeature::Avx512Vpopcntdq),
+            (16, CpuFeature::La57),
+            (22, CpuFeature::Rdpid),
+            (23, CpuFeature::Kl),
+            (24, CpuFeature::BusLockDetect),
+            (25, CpuFeature::Cldemote),
+            (27, CpuFeature::Movdiri),
+            (28, CpuFeature::Movdir64b),
+            (29, CpuFeature::Enqcmd),
+            (30, CpuFeature::SgxLc),
+            (31, CpuFeature::Pks),
+        ],
+    ),
+    (
+        0x7,
+        0,
+        Register::Edx,
+        &[
+            (2, CpuFeature::Avx512_4vnniw),
+            (3, CpuFeature::Avx512_4fmaps),
+            (4, CpuFeature::Fsrm),
+            (8, CpuFeature::Avx512Vp2intersect),
+            (9, CpuFeature::SrbdsCtrl),
+            (10, CpuFeature::MdClear),
+            (11, CpuFeature::RtmAlwaysAbort),
+            (13, CpuFeature::TsxForceAbort),
+            (14, CpuFeature::Serialize),
+            (15, CpuFeature::HybridCpu),
+            (16, CpuFeature::Tsxldtrk),
+            (18, CpuFeature::Pconfig),
+            (19, CpuFeature::ArchLbr),
+            (20, CpuFeature::Ibt),
+            (22, CpuFeature::AmxBf16),
+            (23, CpuFeature::Avx512Fp16),
+            (24, CpuFeature::AmxTile),
+            (25, CpuFeature::AmxInt8),
+            (26, CpuFeature::SpecCtrl),
+            (27, CpuFeature::IntelStibp),
+            (28, CpuFeature::FlushL1d),
+            (29, CpuFeature::ArchCapabilities),
+            (30, CpuFeature::CoreCapabilities),
+            (31, CpuFeature::SpecCtrlSsbd),
+        ],
+    ),
+    (
+        0x7,
+        1,
+        Register::Eax,
+        &[
+            (0, CpuFeature::Sha512),
+            (1, CpuFeature::Sm3),
+            (2, CpuFeature::Sm4),
+            (4, CpuFeature::AvxVnni),
+            (5, CpuFeature::Avx512Bf16),
+            (7, CpuFeature::Cmpccxadd),
+            (10, CpuFeature::Fzrm),
+            (11, CpuFeature::Fsrs),
+            (12, CpuFeature::Fsrc),
+            (17, CpuFeature::Fred),
+            (18, CpuFeature::Lkgs),
+            (19, CpuFeature::Wrmsrns),
+            (21, CpuFeature::AmxFp16),
+            (22, CpuFeature::Hreset),
+            (23, CpuFeature::AvxIfma),
+            (26, CpuFeature::Lam),
+            (27, CpuFeature::Msrlist),
+        ],
+    ),
+    (
+        0x7,
+        1,
+        Register::Edx,
+        &[
+            (4, CpuFeature::AvxVnniInt8),
+            (5, CpuFeature::AvxNeConvert),
+            (8, CpuFeature::AmxComplex),
+            (10, CpuFeature::AvxVnniInt16),
+            (14, CpuFeature::Prefetchiti),
+            (18, CpuFeature::CetSss),
+            (19, CpuFeature::Avx10),
+            (21, CpuFeature::ApxF),
+        ],
+    ),
+    (
+        0x7,
+        2,
+        Register::Edx,
+        &[
+            (0, CpuFeature::Psfd),
+            (1, CpuFeature::IpredCtrl),
+            (2, CpuFeature::RrsbaCtrl),
+            (3, CpuFeature::DdpdU),
+            (4, CpuFeature::BhiCtrl),
+            (5, CpuFeature::McdtNo),
+        ],
+    ),
+    (
+        0xD,
+        1,
+        Register::Eax,
+        &[
+            (0, CpuFeature::Xsaveopt),
+            (1, CpuFeature::Xsavec),
+            (2, CpuFeature::Xgetbv1),
+            (3, CpuFeature::Xsaves),
+            (4, CpuFeature::Xfd),
+        ],
+    ),
+    (
+        0x14,
+        0,
+        Register::Ebx,
+        &[
+            (0, CpuFeature::PtCr3Filtering),
+            (1, CpuFeature::PtPsbCyc),
+            (2, CpuFeature::PtIpFiltering),
+            (3, CpuFeature::PtMtc),
+            (4, CpuFeature::PtPtwrite),
+            (5, CpuFeature::PtPowerEventTrace),
+            (6, CpuFeature::PtPsbPmiPreservation),
+            (7, CpuFeature::PtEventTrace),
+            (8, CpuFeature::PtTntDisable),
+        ],
+    ),
+    (
+        0x14,
+        0,
+        Register::Ecx,
+        &[
+            (0, CpuFeature::PtTopa),
+            (1, CpuFeature::PtTopaMultiple),
+            (2, CpuFeature::PtSingleRange),
+            (3, CpuFeature::PtTraceTransport),
+            (31, CpuFeature::PtLip),
+        ],
+    ),
+    (
+        0x19,
+        0,
+        Register::Ebx,
+        &[
+            (0, CpuFeature::Aeskle),
+            (2, CpuFeature::WideKl),
+            (4, CpuFeature::KlIwkeyBackup),
+        ],
+    ),
+    (
+        0x8000_0008,
+        0,
+        Register::Ebx,
+        &[
+            (0, CpuFeature::Clzero),
+            (1, CpuFeature::Irperf),
+            (2, CpuFeature::Xsaveerptr),
+            (4, CpuFeature::Rdpru),
+            (8, CpuFeature::Mcommit),
+            (9, CpuFeature::Wbnoinvd),
+            (12, CpuFeature::AmdIbpb),
+            (14, CpuFeature::AmdIbrs),
+            (15, CpuFeature::AmdStibp),
+            (17, CpuFeature::AmdStibpAlwaysOn),
+            (23, CpuFeature::AmdPpin),
+            (24, CpuFeature::AmdSsbd),
+            (25, CpuFeature::VirtSsbd),
+            (26, CpuFeature::AmdSsbNo),
+            (27, CpuFeature::Cppc),
+            (28, CpuFeature::AmdPsfd),
+            (29, CpuFeature::BtcNo),
+            (30, CpuFeature::AmdIbpbRet),
+        ],
+    ),
+    (
+        0x8000_001F,
+        0,
+        Register::Eax,
+        &[
+            (0, CpuFeature::Sme),
+            (1, CpuFeature::Sev),
+            (2, CpuFeature::VmPageFlush),
+            (3, CpuFeature::SevEs),
+            (4, CpuFeature::SevSnp),
+            (9, CpuFeature::VTscAux),
+            (10, CpuFeature::SmeCoherent),
+            (14, CpuFeature::DebugSwap),
+        ],
+    ),
+];
+
+/// Anything that can answer CPUID queries: the real instruction, a recorded
+/// dump or a mock. Closures `Fn(leaf, subleaf) -> CpuidResult` work as mocks.
+pub trait CpuidSource {
+    fn cpuid(&self, leaf: u32, subleaf: u32) -> CpuidResult;
+
+    /// XCR0 as returned by XGETBV, if the source can provide it.
+    fn xcr0(&self) -> Option<u64> {
+        None
+    }
+}
+
+impl<F> CpuidSource for F
+where
+    F: Fn(u32, u32) -> CpuidResult,
+{
+    fn cpuid(&self, leaf: u32, subleaf: u32) -> CpuidResult {
+        self(leaf, subleaf)
+    }
+}
+
+/// Executes the CPUID instruction on the current CPU.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct NativeCpuid;
+
+impl CpuidSource for NativeCpuid {
+    #[cfg(target_arch = "x86_64")]
+    fn cpuid(&self, leaf: u32, subleaf: u32) -> CpuidResult {
+        let r = core::arch::x86_64::__cpuid_count(leaf, subleaf);
+        CpuidResult::new(r.eax, r.ebx, r.ecx, r.edx)
+    }
+
+    #[cfg(target_arch = "x86")]
+    fn cpuid(&self, leaf: u32, subleaf: u32) -> CpuidResult {
+        let r = core::arch::x86::__cpuid_count(leaf, subleaf);
+        CpuidResult::new(r.eax, r.ebx, r.ecx, r.edx)
+    }
+
+    #[cfg(not(any(target_arch = "x86_64", target_arch = "x86")))]
+    fn cpuid(&self, _leaf: u32, _subleaf: u32) -> CpuidResult {
+        CpuidResult::default()
+    }
+
+    #[cfg(any(target_arch = "x86_64", target_arch = "x86"))]
+    fn xcr0(&self) -> Option<u64> {
+        #[cfg(target_arch = "x86")]
+        use core::arch::x86::_xgetbv;
+        #[cfg(target_arch = "x86_64")]
+        use core::arch::x86_64::_xgetbv;
+
+        //XGETBV faults unless the OS has set CR4.OSXSAVE
+        if self.cpuid(1, 0).ecx & (1 << 27) == 0 {
+            return None;
+        }
+        Some(unsafe { _xgetbv(0) })
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Signature {
+    pub family: u8,
+    pub model: u8,
+    pub stepping: u8,
+}
+
+/// Platform-neutral decoding of x86 CPUID leaves.
+pub struct CpuidDecoder<'a, S: CpuidSource + ?Sized> {
+    source: &'a S,
+    max_basic: u32,
+    max_hypervisor: u32,
+    max_extended: u32,
+}
+
+impl<'a, S: CpuidSource + ?Sized> CpuidDecoder<'a, S> {
+    pub fn new(source: &'a S) -> Self {
+        let max_basic = source.cpuid(0, 0).eax;
+        let max_extended = source.cpuid(0x8000_0000, 0).eax;
+        let max_extended = if max_extended >= 0x8000_0000 {
+            max_extended
+        } else {
+            0
+        };
+        //Hypervisor leaves are only meaningful when leaf 0x1 reports a hypervisor
+        let hypervisor_present = max_basic >= 1 && source.cpuid(1, 0).ecx & (1 << 31) != 0;
+        let max_hypervisor = if hypervisor_present {
+            //Old KVM reports 0 for its single feature leaf
+            match source.cpuid(0x4000_0000, 0).eax {
+                max if max < 0x4000_0000 => 0x4000_0001,
+                max => max.min(0x4000_00FF),
+            }
+        } else {
+            0
+        };
+        Self {
+            source,
+            max_basic,
+            max_hypervisor,
+            max_extended,
+        }
+    }
+
+    pub fn max_basic_leaf(&self) -> u32 {
+        self.max_basic
+    }
+
+    pub fn max_extended_leaf(&self) -> u32 {
+        self.max_extended
+    }
+
+    /// Highest leaf of the hypervisor interface at 0x40000000, 0 on bare
+    /// metal.
+    pub fn max_hypervisor_leaf(&self) -> u32 {
+        self.max_hypervisor
+    }
+
+    /// Base and highest leaf of every hypervisor interface. A hypervisor that
+    /// emulates another one's interface at 0x40000000, like Xen or KVM with
+    /// Hyper-V enlightenments, moves its own up in steps of 0x100.
+    pub fn hypervisor_bases(&self) -> Vec<(u32, u32)> {
+        (0x4000_0000..0x4001_0000)
+            .step_by(0x100)
+            .map(|base| (base, self.max_hypervisor_leaf_at(base)))
+            .filter(|&(_, max)| max != 0)
+            .collect()
+    }
+
+    fn max_hypervisor_leaf_at(&self, base: u32) -> u32 {
+        if base == 0x4000_0000 || self.max_hypervisor == 0 {
+            return self.max_hypervisor;
+        }
+        let max = self.source.cpuid(base, 0).eax;
+        if (base..=base + 0xFF).contains(&max) {
+            max
+        } else {
+            0
+        }
+    }
+
+    pub fn is_available(&self) -> bool {
+        !self.source.cpuid(0, 0).is_zero()
+    }
+
+    /// Queries a leaf, returning `None` when it is above the maximum leaf of
+    /// its range. Out of range leaves return unrelated data on real CPUs.
+    /// Hypervisor leaves are `None` on bare metal.
+    pub fn leaf(&self, leaf: u32, subleaf: u32) -> Option<CpuidResult> {
+        let max = match leaf {
+            0x8000_0000.. => self.max_extended,
+            0x4000_0000..=0x4000_FFFF => self.max_hypervisor_leaf_at(leaf & !0xFF),
+            0x4001_0000..=0x4FFF_FFFF => 0,
+            _ => self.max_basic,
+        };
+        (leaf <= max).then(|| self.source.cpuid(leaf, subleaf))
+    }
+
+    pub fn vendor(&self) -> String {
+        let r = self.source.cpuid(0, 0);
+        let mut vendor = Vec::new();
+        vendor.extend_from_slice(&r.ebx.to_le_bytes());
+        vendor.extend_from_slice(&r.edx.to_le_bytes());
+        vendor.extend_from_slice(&r.ecx.to_le_bytes());
+        String::from_utf8_lossy(&vendor).trim().to_string()
+    }
+
+    pub fn brand(&self) -> Option<String> {
+        if self.max_extended < 0x8000_0004 {
+            return None;
+        }
+        let mut brand = String::new();
+        for leaf in 0x8000_0002..=0x8000_0004 {
+            let r = self.source.cpuid(leaf, 0);
+            for reg in [r.eax, r.ebx, r.ecx, r.edx] {
+                brand.push_str(&String::from_utf8_lossy(&reg.to_le_bytes()));
+            }
+        }
+        Some(brand.trim_matches(char::from(0)).trim().to_string())
+    }
+
+    pub fn signature(&self) -> Signature {
+        let eax = self.leaf(1, 0).unwrap_or_default().eax;
+        let model = ((eax >> 4) & 0xF) as u8;
+        let family = ((eax >> 8) & 0xF) as u8;
+        let ext_model = ((eax >> 16) & 0xF) as u8;
+        let ext_family = ((eax >> 20) & 0xFF) as u8;
+        Signature {
+            family: if family == 0xF {
+                family.saturating_add(ext_family)
+            } else {
+                family
+            },
+            model: if family == 0x6 || family == 0xF {
+                (ext_model << 4) + model
+            } else {
+                model
+            },
+            stepping: (eax & 0xF) as u8,
+        }
+    }
+
+    /// Every feature bit of the leaves listed in `FEATURE_BITS`.
+    pub fn features(&self) -> CpuFeatures {
+        let mut flags = CpuFeatures::new();
+        let max_subleaf_7 = self.leaf(7, 0).unwrap_or_default().eax;
+        for &(leaf, subleaf, register, bits) in FEATURE_BITS {
+            if leaf == 7 && subleaf > max_subleaf_7 {
+                continue;
+            }
+            let Some(r) = self.leaf(leaf, subleaf) else {
+                continue;
+            };
+            let value = r.register(register);
+            for &(bit, feature) in bits {
+                flags.set(feature, value & (1 << bit) != 0);
+            }
+        }
+        flags.set(CpuFeature::TdxGuest, self.is_tdx_guest());
+        flags
+    }
+
+    /// Physical core and logical thread counts of one package.
+    pub fn topology(&self) -> (usize, usize, Provenance) {
+        let mut logical = 0u32;
+        let mut smt = 0u32;
+
+        let mut try_leaf = |leaf: u32| -> bool {
+            if self.max_basic < leaf {
+                return false;
+            }
+            let mut sub = 0u32;
+            loop {
+                let r = self.source.cpuid(leaf, sub);
+                let level_type = (r.ecx >> 8) & 0xff;
+                let level_count = r.ebx & 0xffff;
+                if level_count == 0 {
+                    break;
+                }
+                if level_type == 1 {
+                    smt = level_count;
+                }
+                if level_type == 2 {
+                    logical = level_count;
+                }
+                if (r.eax & 0x1f) == 0 {
+                    break;
+                }
+                sub += 1;
+            }
+            logical != 0
+        };
+
+        let topology_leaf = if try_leaf(0x1f) {
+            Some(0x1f)
+        } else if try_leaf(0x0b) {
+            Some(0x0b)
+        } else {
+            None
+        };
+
+        if let Some(leaf) = topology_leaf {
+            let smt_eff = smt.max(1);
+            let phys = (logical / smt_eff).max(1);
+            return (
+                phys as usize,
+                logical as usize,
+                Provenance::new(Source::Cpuid { leaf }, Confidence::High),
+            );
+        }
+
+        let vendor = self.vendor();
+        let lpp = (self.leaf(1, 0).unwrap_or_default().ebx >> 16) & 0xff;
+        if self.max_extended >= 0x8000_0008 && vendor == "AuthenticAMD" {
+            let phys = (self.source.cpuid(0x8000_0008, 0).ecx & 0xff) + 1;
+            let l = if lpp != 0 { lpp } else { phys };
+            (
+                phys as usize,
+                l as usize,
+                Provenance::new(Source::Cpuid { leaf: 0x8000_0008 }, Confidence::Medium),
+            )
+        } else if self.max_basic >= 4 && vendor == "GenuineIntel" {
+            let mut i = 0u32;
+            let mut max_phys_minus1 = 0u32;
+            loop {
+                let eax = self.source.cpuid(4, i).eax;
+                if (eax & 0x1f) == 0 {
+                    break;
+                }
+                let c = (eax >> 26) & 0x3f;
+                if c > max_phys_minus1 {
+                    max_phys_minus1 = c;
+                }
+                i += 1;
+            }
+            let phys = (max_phys_minus1 + 1).max(1);
+            let l = if lpp != 0 { lpp } else { phys };
+            (
+                phys as usize,
+                l as usize,
+                Provenance::new(Source::Cpuid { leaf: 4 }, Confidence::Low),
+            )
+        } else {
+            let l = if lpp != 0 { lpp as usize } else { 1 };
+            (
+                1,
+                l,
+                Provenance::new(Source::Cpuid { leaf: 1 }, Confidence::Low),
+            )
+        }
+    }
+
+    /// Whether leaf 0x7 reports a hybrid CPU with several core types.
+    pub fn is_hybrid(&self) -> bool {
+        self.leaf(7, 0).unwrap_or_default().edx & (1 << 15) != 0
+    }
+
+    /// Core type of the CPU executing CPUID, from leaf 0x1A.
+    pub fn core_type(&self) -> Option<CoreType> {
+        if !self.is_hybrid() {
+            return None;
+        }
+        match self.leaf(0x1A, 0)?.eax >> 24 {
+            0x40 => Some(CoreType::Performance),
+            0x20 => Some(CoreType::Efficiency),
+            _ => None,
+        }
+    }
+
+    fn extended_topology_leaf(&self) -> Option<u32> {
+        [0x1F, 0x0B]
+            .into_iter()
+            .find(|&leaf| self.leaf(leaf, 0).is_some_and(|r| r.ebx & 0xFFFF != 0))
+    }
+
+    /// APIC ID of the CPU executing CPUID, x2APIC when available.
+    pub fn apic_id(&self) -> u32 {
+        match self.extended_topology_leaf() {
+            Some(leaf) => self.source.cpuid(leaf, 0).edx,
+            None => self.leaf(1, 0).unwrap_or_default().ebx >> 24,
+        }
+    }
+
+    /// APIC ID bits that select the thread within a core.
+    pub fn smt_shift(&self) -> u32 {
+        let Some(leaf) = self.extended_topology_leaf() else {
+            return 0;
+        };
+        let r = self.source.cpuid(leaf, 0);
+        if (r.ecx >> 8) & 0xFF == 1 {
+            r.eax & 0x1F
+        } else {
+            0
+        }
+    }
+
+    pub fn cache_leaf(&self) -> Option<u32> {
+        if self.max_extended >= 0x8000_001D {
+            Some(0x8000_001D)
+        } else if self.max_basic >= 4 {
+            Some(4)
+        } else {
+            None
+        }
+    }
+
+    /// Caches of the CPU executing CPUID, from leaf 0x4 or 0x8000001D.
+    pub(crate) fn cache_parameters(&self) -> Vec<CacheParameters> {
+        let Some(leaf) = self.cache_leaf() else {
+            return Vec::new();
+        };
+        let mut caches = Vec::new();
+        for sub in 0..32 {
+            let r = self.source.cpuid(leaf, sub);
+            let cache_type = match r.eax & 0x1F {
+                0 => break,
+                1 => CacheType::Data,
+                2 => CacheType::Instruction,
+                3 => CacheType::Unified,
+                _ => continue,
+            };
+            let line_size = (r.ebx & 0xFFF) + 1;
+            let partitions = ((r.ebx >> 12) & 0x3FF) + 1;
+            let ways = ((r.ebx >> 22) & 0x3FF) + 1;
+            let sets = r.ecx.saturating_add(1);
+            let sharing = ((r.eax >> 14) & 0xFFF) + 1;
+            caches.push(CacheParameters {
+                descriptor: CacheDescriptor {
+                    level: ((r.eax >> 5) & 0x7) as u8,
+                    cache_type,
+                    size: [partitions, line_size, sets]
+                        .into_iter()
+                        .fold(ways as usize, |size, n| size.saturating_mul(n as usize)),
+                    ways: if r.eax & (1 << 9) != 0 { 0 } else { ways },
+                    line_size,
+                    sets,
+                    partitions,
+                    inclusive: Some(r.edx & (1 << 1) != 0),
+                    shared_cpus: Vec::new(),
+                },
+                sharing_shift: sharing.next_power_of_two().trailing_zeros(),
+            });
+        }
+        caches
+    }
+
+    /// Cache instances of a package with the given core and thread counts.
+    /// Only this CPU's leaves are read, so CPUs are assumed to be numbered
+    /// core by core, with the APIC ID layout of this CPU.
+    pub fn caches(&self, cores: usize, threads: usize) -> Vec<CacheDescriptor> {
+        let parameters = self.cache_parameters();
+        let threads = threads.max(1);
+        let smt = (threads / cores.max(1)).max(1);
+        let smt_shift = match self.smt_shift() {
+            0 => smt.next_power_of_two().trailing_zeros(),
+            shift => shift,
+        };
+        cache_instances((0..threads).map(|cpu| {
+            let apic_id = (((cpu / smt) << smt_shift) | (cpu % smt)) as u32;
+            (cpu, apic_id, parameters.as_slice())
+        }))
+    }
+
+    /// Base and maximum frequency in MHz from leaf 0x16.
+    pub fn frequencies(&self) -> (u32, u32) {
+        let r = self.leaf(0x16, 0).unwrap_or_default();
+        (r.eax & 0xFFFF, r.ebx & 0xFFFF)
+    }
+}
+
+/// Builds everything `CpuData` can hold from CPUID alone, skipping what
+/// `options` does not want. OS backends call this first and then add or
+/// replace what their OS knows better.
+pub(crate) fn decode_cpu_data<S: CpuidSource + ?Sized>(
+    source: &S,
+    options: &ProbeOptions,
+) -> Result<CpuData, ProbeError> {
+    let decoder = CpuidDecoder::new(source);
+    if !decoder.is_available() {
+        return Err(ProbeError::CpuidUnavailable);
+    }
+
+    let long_mode = decoder
+        .leaf(0x8000_0001, 0)
+        .is_some_and(|r| r.edx & (1 << 29) != 0);
+    let mut results = CpuData {
+        arch: if long_mode { "x86_64" } else { "x86" }.to_string(),
+        ..Default::default()
+    };
+    results.record(
+        CpuField::Architecture,
+        Source::Cpuid { leaf: 0x8000_0001 },
+        Confidence::High,
+    );
+
+    //Vendor
+    results.vendor = decoder.vendor();
+    results.record(
+        CpuField::Vendor,
+        Source::Cpuid { leaf: 0 },
+        Confidence::High,
+    );
+
+    //Brand
+    match decoder.brand() {
+        Some(brand) => {
+            results.brand = brand;
+            results.record(
+                CpuField::Brand,
+                Source::Cpuid { leaf: 0x8000_0002 },
+                Confidence::High,
+            );
+        }
+        None => results.warn(
+            CpuField::Brand,
+            "CPUID leaves 0x80000002-0x80000004 unsupported",
+        ),
+    }
+
+    //Family/Model/Stepping
+    let signature = decoder.signature();
+    results.family = signature.family;
+    results.model = signature.model;
+    results.stepping = signature.stepping;
+    for field in [CpuField::Family, CpuField::Model, CpuField::Stepping] {
+        results.record(field, Source::Cpuid { leaf: 1 }, Confidence::High);
+    }
+
+    //Hypervisor
+    results.hypervisor = decoder.hypervisor();
+    let leaf = if results.hypervisor.is_some() {
+        0x4000_0000
+    } else {
+        1
+    };
+    results.record(
+        CpuField::Hypervisor,
+        Source::Cpuid { leaf },
+        Confidence::High,
+    );
+
+    //Address widths
+    let (address_widths, reported) = decoder.address_widths();
+    results.address_widths = address_widths;
+    if reported {
+        results.record(
+            CpuField::AddressWidths,
+            Source::Cpuid { leaf: 0x8000_0008 },
+            Confidence::High,
+        );
+    } else {
+        results.record(
+            CpuField::AddressWidths,
+            Source::Cpuid { leaf: 1 },
+            Confidence::Medium,
+        );
+    }
+
+    //Flags
+    if options.wants(CpuField::Flags) {
+        results.flags = decoder.features();
+        if decoder.max_basic_leaf() >= 7 {
+            results.record(
+                CpuField::Flags,
+                Source::CpuidFeatureLeaves,
+                Confidence::High,
+            );
+        } else {
+            results.warn(CpuField::Flags, "CPUID leaf 0x7 unsupported");
+        }
+    }
+
+    //XSAVE state
+    if options.wants(CpuField::XsaveState) {
+        let osxsave = decoder.leaf(1, 0).unwrap_or_default().ecx & (1 << 27) != 0;
+        match source.xcr0() {
+            Some(xcr0) => {
+                results.enabled_state = xcr0;
+                results.usable_state = xcr0;
+                results.record(CpuField::XsaveState, Source::Xgetbv, Confidence::High);
+            }
+            //No OSXSAVE means no extended state is enabled at all
+            None if !osxsave => {
+                results.record(
+                    CpuField::XsaveState,
+                    Source::Cpuid { leaf: 1 },
+                    Confidence::High,
+                );
+            }
+            None => results.warn(
+                CpuField::XsaveState,
+                "XCR0 not available from this source, x86-64 levels assume CPUID support",
+            ),
+        }
+    }
+
+    //Topology, which cache instances depend on
+    if options.wants(CpuField::Topology) || options.wants(CpuField::Caches) {
+        let (cores, threads, provenance) = decoder.topology();
+        results.cores = cores;
+        results.threads = threads;
+        results.provenance.insert(CpuField::Topology, provenance);
+    }
+    if options.wants(CpuField::Caches) {
+        results.set_caches(&decoder);
+    }
+
+    //TLBs
+    if options.wants(CpuField::Tlb) {
+        match decoder.tlbs() {
+            Some((leaf, tlbs)) => {
+                results.tlbs = tlbs;
+                results.record(CpuField::Tlb, Source::Cpuid { leaf }, Confidence::High);
+            }
+            None => results.warn(CpuField::Tlb, "no CPUID TLB descriptors reported"),
+        }
+    }
+
+    //Clock
+    if options.wants(CpuField::ClockSpeed) || options.wants(CpuField::ClockSpeedTurbo) {
+        let (base, turbo) = decoder.frequencies();
+        results.clock_speed = base;
+        results.clock_speed_turbo = turbo;
+        for (field, value) in [
+            (CpuField::ClockSpeed, base),
+            (CpuField::ClockSpeedTurbo, turbo),
+        ] {
+            if value != 0 {
+                results.record(field, Source::Cpuid { leaf: 0x16 }, Confidence::High);
+            } else {
+                results.warn(field, "CPUID leaf 0x16 unsupported");
+            }
+        }
+    }
+
+    //TSC
+    if options.wants(CpuField::TscFrequency) {
+        match decoder.tsc_frequency() {
+            Some((hz, provenance)) => {
+                results.tsc_frequency = hz;
+                results.resolve(CpuField::TscFrequency, provenance);
+            }
+            None => results.warn(
+                CpuField::TscFrequency,
+                "CPUID leaf 0x15 reports no TSC ratio",
+            ),
+        }
+    }
+
+    //Security
+    if options.wants(CpuField::Security) {
+        results.sgx = decoder.sgx();
+        results.memory_encryption = decoder.memory_encryption();
+        results.record(
+            CpuField::Security,
+            Source::CpuidFeatureLeaves,
+            Confidence::High,
+        );
+    }
+
+    Ok(results)
+}
+
+impl CpuData {
+    /// Decodes a `CpuData` from any CPUID source, without OS-specific data
+    /// such as the microcode revision.
+    pub fn from_cpuid<S: CpuidSource + ?Sized>(source: &S) -> Result<CpuData, ProbeError> {
+        decode_cpu_data(source, &ProbeOptions::default())
+    }
+
+    /// Recomputes cache instances for the current core and thread counts.
+    pub(crate) fn set_caches<S: CpuidSource + ?Sized>(&mut self, decoder: &CpuidDecoder<S>) {
+        self.caches = decoder.caches(self.cores, self.threads);
+        match decoder.cache_leaf() {
+            Some(leaf) if !self.caches.is_empty() => {
+                self.record(CpuField::Caches, Source::Cpuid { leaf }, Confidence::Medium)
+            }
+            _ => self.warn(CpuField::Caches, "no CPUID cache descriptors reported"),
+        }
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+    use crate::cpu_info::HypervisorVendor;
+
+    /// "GenuineIntel" as leaf 0 returns it in EBX, ECX and EDX.
+    const INTEL: [u32; 3] = [0x756E_6547, 0x6C65_746E, 0x4965_6E69];
+
+    /// CPUID source answering from a table of `(leaf, subleaf, [eax, ebx,
+    /// ecx, edx])`, zero for anything else.
+    pub(crate) fn table(
+        leaves: &[(u32, u32, [u32; 4])],
+    ) -> impl Fn(u32, u32) -> CpuidResult + use<> {
+        let leaves: HashMap<(u32, u32), CpuidResult> = leaves
+            .iter()
+            .map(|&(leaf, subleaf, [eax, ebx, ecx, edx])| {
+                ((leaf, subleaf), CpuidResult::new(eax, ebx, ecx, edx))
+            })
+            .collect();
+        move |leaf, subleaf| leaves.get(&(leaf, subleaf)).copied().unwrap_or_default()
+    }
+
+    pub(crate) fn intel(
+        max_basic: u32,
+        leaves: &[(u32, u32, [u32; 4])],
+    ) -> impl Fn(u32, u32) -> CpuidResult + use<> {
+        let [ebx, ecx, edx] = INTEL;
+        let mut leaves = leaves.to_vec();
+        leaves.push((0, 0, [max_basic, ebx, ecx, edx]));
+        table(&leaves)
+    }
+
+    fn signature(eax: u32) -> Signature {
+        CpuidDecoder::new(&table(&[(0, 0, [1, 0, 0, 0]), (1, 0, [eax, 0, 0, 0])])).signature()
+    }
+
+    #[test]
+    fn signature_combines_extended_family_and_model() {
+        let coffee_lake = signature(0x0009_06EA);
+        assert_eq!(
+            (coffee_lake.family, coffee_lake.model, coffee_lake.stepping),
+            (0x6, 0x9E, 0xA)
+        );
+
+        let zen3 = signature(0x00A2_0F10);
+        assert_eq!((zen3.family, zen3.model, zen3.stepping), (0x19, 0x21, 0x0));
+
+        //The extended model only applies to families 0x6 and 0xF
+        let k6 = signature(0x0001_0580);
+        assert_eq!((k6.family, k6.model), (0x5, 0x8));
+    }
+
+    #[test]
+    fn signature_saturates_family_overflow() {
+        assert_eq!(signature(0x0FF0_0F00).family, u8::MAX);
+    }
+
+    #[test]
+    fn features_decode_leaf_bits() {
+        let source = intel(
+            7,
+            &[
+                (1, 0, [0, 0, 1 << 28, 1 << 26]),
+                (7, 0, [0, 1 << 5, 0, 0]),
+                (7, 1, [1, 0, 0, 0]),
+            ],
+        );
+        let features = CpuidDecoder::new(&source).features();
+        assert!(features.has(CpuFeature::Sse2));
+        assert!(features.has(CpuFeature::Avx));
+        assert!(features.has(CpuFeature::Avx2));
+        assert!(!features.has(CpuFeature::Avx512f));
+        //Subleaf 1 is beyond the highest subleaf leaf 7 reports
+        assert!(!features.has(CpuFeature::Sha512));
+    }
+
+    #[test]
+    fn features_skip_leaves_above_the_maximum() {
+        let source = intel(1, &[(1, 0, [0, 0, 0, 1 << 26]), (7, 0, [0, 1 << 5, 0, 0])]);
+        let features = CpuidDecoder::new(&source).features();
+        assert!(features.has(CpuFeature::Sse2));
+        assert!(!features.has(CpuFeature::Avx2));
+    }
+
+    /// Leaf 0x4 EAX and EBX of a cache: type, level, CPUs sharing it and its
+    /// geometry.
+    pub(crate) fn cache_leaf(
+        cache_type: u32,
+        level: u32,
+        sharing: u32,
+        ways: u32,
+        line_size: u32,
+        sets: u32,
+    ) -> [u32; 4] {
+        [
+            cache_type | (level << 5) | ((sharing - 1) << 14),
+            ((ways - 1) << 22) | (line_size - 1),
+            sets - 1,
+            0,
+        ]
+    }
+
+    #[test]
+    fn cache_parameters_decode_leaf_4() {
+        let source = intel(
+            4,
+            &[
+                (4, 0, cache_leaf(1, 1, 2, 12, 64, 64)),
+                (4, 1, cache_leaf(2, 1, 2, 8, 64, 64)),
+                (4, 2, cache_leaf(3, 2, 2, 10, 64, 2048)),
+                (4, 3, cache_leaf(3, 3, 32, 12, 64, 49152)),
+            ],
+        );
+        let caches = CpuidDecoder::new(&source).cache_parameters();
+        let decoded: Vec<(u8, CacheType, usize, u32)> = caches
+            .iter()
+            .map(|c| {
+                let d = &c.descriptor;
+                (d.level, d.cache_type, d.size, c.sharing_shift)
+            })
+            .collect();
+        assert_eq!(
+            decoded,
+            [
+                (1, CacheType::Data, 48 << 10, 1),
+                (1, CacheType::Instruction, 32 << 10, 1),
+                (2, CacheType::Unified, 1280 << 10, 1),
+                (3, CacheType::Unified, 36 << 20, 5),
+            ]
+        );
+    }
+
+    #[test]
+    fn cache_parameters_survive_oversized_geometry() {
+        let source = intel(4, &[(4, 0, [0x21, u32::MAX, u32::MAX, 0])]);
+        let caches = CpuidDecoder::new(&source).cache_parameters();
+        assert_eq!(caches.len(), 1);
+        assert!(caches[0].descriptor.size >= u32::MAX as usize);
+    }
+
+    #[test]
+    fn apic_shifts_from_extended_topology() {
+        //2 threads per core, 8 threads per package
+        let source = intel(
+            0xB,
+            &[
+                (1, 0, [0, 0, 0, 1 << 28]),
+                (0xB, 0, [1, 2, 1 << 8, 0]),
+                (0xB, 1, [4, 8, (2 << 8) | 1, 0]),
+            ],
+        );
+        let (leaf, shifts) = CpuidDecoder::new(&source).apic_shifts();
+        assert_eq!(leaf, 0xB);
+        //Package 1, core 1, thread 1
+        assert_eq!(shifts.ids(0b1_0011), [1, 0, 0, 1]);
+        assert_eq!(shifts.ids(0b0_1110), [0, 0, 0, 7]);
+    }
+
+    #[test]
+    fn apic_shifts_from_legacy_leaves() {
+        //16 logical CPUs and 8 cores per package
+        let source = intel(
+            4,
+            &[
+                (1, 0, [0, 16 << 16, 0, 1 << 28]),
+                (4, 0, [(7 << 26) | 0x21, 0, 0, 0]),
+            ],
+        );
+        let (leaf, shifts) = CpuidDecoder::new(&source).apic_shifts();
+        assert_eq!(leaf, 4);
+        assert_eq!(shifts.ids(0b1_0111), [1, 0, 0, 3]);
+    }
+
+    #[test]
+    fn apic_shifts_without_htt() {
+        let source = intel(1, &[(1, 0, [0, 0, 0, 0])]);
+        let (leaf, shifts) = CpuidDecoder::new(&source).apic_shifts();
+        assert_eq!(leaf, 1);
+        assert_eq!(shifts.ids(3), [3, 0, 0, 0]);
+    }
+
+    /// Hypervisor leaf at `base` with its signature in EBX, ECX and EDX.
+    pub(crate) fn hypervisor_leaf(
+        base: u32,
+        max_leaf: u32,
+        signature: &[u8; 12],
+    ) -> (u32, u32, [u32; 4]) {
+        let register = |i: usize| u32::from_le_bytes(signature[i..i + 4].try_into().unwrap());
+        (base, 0, [max_leaf, register(0), register(4), register(8)])
+    }
+
+    #[test]
+    fn hypervisor_above_emulated_hyper_v() {
+        //Xen with Viridian moves its own leaves to 0x40000100
+        let source = intel(
+            1,
+            &[
+                (1, 0, [0, 0, 1 << 31, 0]),
+                hypervisor_leaf(0x4000_0000, 0x4000_0005, b"Microsoft Hv"),
+                hypervisor_leaf(0x4000_0100, 0x4000_0105, b"XenVMMXenVMM"),
+                (0x4000_0101, 0, [0x0004_0011, 0, 0, 0]),
+                (0x4000_0104, 0, [0b11, 0, 0, 0]),
+            ],
+        );
+        let decoder = CpuidDecoder::new(&source);
+        assert_eq!(
+            decoder.hypervisor_bases(),
+            [(0x4000_0000, 0x4000_0005), (0x4000_0100, 0x4000_0105)]
+        );
+        let xen = decoder.hypervisor().unwrap();
+        assert_eq!(xen.vendor(), HypervisorVendor::Xen);
+        assert_eq!(xen.max_leaf(), 0x4000_0105);
+        assert_eq!(xen.version(), Some("4.17"));
+        assert_eq!(xen.features(), ["virt_apic", "virt_x2apic"]);
+    }
+
+    #[test]
+    fn hypervisor_leaves_need_the_hypervisor_bit() {
+        let source = intel(
+            1,
+            &[
+                (1, 0, [0, 0, 0, 0]),
+                hypervisor_leaf(0x4000_0000, 0x4000_0001, b"KVMKVMKVM\0\0\0"),
+            ],
+        );
+        let decoder = CpuidDecoder::new(&source);
+        assert!(decoder.hypervisor_bases().is_empty());
+        assert!(decoder.hypervisor().is_none());
+    }
+}
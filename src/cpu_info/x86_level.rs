@@ -0,0 +1,168 @@
+use std::fmt;
+
+use crate::cpu_info::{CpuData, CpuFeature, FeatureSupport};
+
+/// Microarchitecture level of the x86-64 psABI.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum X86Level {
+    V1,
+    V2,
+    V3,
+    V4,
+}
+
+impl X86Level {
+    pub const ALL: [X86Level; 4] = [X86Level::V1, X86Level::V2, X86Level::V3, X86Level::V4];
+
+    /// Features this level adds on top of the previous one.
+    pub fn features(self) -> &'static [CpuFeature] {
+        use CpuFeature::*;
+        match self {
+            X86Level::V1 => &[Lm, Cmov, Cx8, Fpu, Fxsr, Mmx, Syscall, Sse, Sse2],
+            X86Level::V2 => &[Cx16, LahfLm, Popcnt, Sse3, Sse4_1, Sse4_2, Ssse3],
+            X86Level::V3 => &[Avx, Avx2, Bmi1, Bmi2, F16c, Fma, Abm, Movbe, Osxsave],
+            X86Level::V4 => &[Avx512f, Avx512bw, Avx512cd, Avx512dq, Avx512vl],
+        }
+    }
+
+    pub fn next(self) -> Option<X86Level> {
+        match self {
+            X86Level::V1 => Some(X86Level::V2),
+            X86Level::V2 => Some(X86Level::V3),
+            X86Level::V3 => Some(X86Level::V4),
+            X86Level::V4 => None,
+        }
+    }
+
+    /// Value for `-C target-cpu=` of rustc.
+    pub fn rustc_target_cpu(self) -> &'static str {
+        match self {
+            X86Level::V1 => "x86-64",
+            _ => self.name(),
+        }
+    }
+
+    /// Value for `-march=` of GCC (11 and newer for v2-v4).
+    pub fn gcc_march(self) -> &'static str {
+        self.rustc_target_cpu()
+    }
+
+    /// Value for `-march=` of Clang (12 and newer for v2-v4).
+    pub fn clang_march(self) -> &'static str {
+        self.rustc_target_cpu()
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            X86Level::V1 => "x86-64-v1",
+            X86Level::V2 => "x86-64-v2",
+            X86Level::V3 => "x86-64-v3",
+            X86Level::V4 => "x86-64-v4",
+        }
+    }
+}
+
+impl fmt::Display for X86Level {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+impl CpuData {
+    /// Highest x86-64 level whose features are all usable by this process,
+    /// or `None` when not even x86-64-v1 is met. Without XCR0 the OS state
+    /// is unknown and CPUID support decides, as `CpuField::XsaveState` warns.
+    pub fn x86_level(&self) -> Option<X86Level> {
+        X86Level::ALL
+            .into_iter()
+            .take_while(|level| self.missing_features(*level).is_empty())
+            .last()
+    }
+
+    /// Features of `level` and every level below it that are not usable.
+    pub fn missing_features(&self, level: X86Level) -> Vec<CpuFeature> {
+        X86Level::ALL
+            .into_iter()
+            .filter(|l| *l <= level)
+            .flat_map(X86Level::features)
+            .copied()
+            .filter(|f| {
+                let support = self.support(*f);
+                support != FeatureSupport::Usable && support != FeatureSupport::Unknown
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cpu_info::CpuField;
+
+    /// Data with every feature up to `level` and XCR0 set to `xcr0`.
+    fn data(level: X86Level, xcr0: u64) -> CpuData {
+        let mut data = CpuData::default();
+        for feature in X86Level::ALL
+            .into_iter()
+            .filter(|l| *l <= level)
+            .flat_map(X86Level::features)
+        {
+            data.flags.insert(*feature);
+        }
+        data.enabled_state = xcr0;
+        data.usable_state = xcr0;
+        data
+    }
+
+    #[test]
+    fn level_table() {
+        assert!(X86Level::V1.features().contains(&CpuFeature::Sse2));
+        assert!(X86Level::V2.features().contains(&CpuFeature::Popcnt));
+        assert!(X86Level::V3.features().contains(&CpuFeature::Osxsave));
+        assert!(X86Level::V4.features().contains(&CpuFeature::Avx512vl));
+        assert_eq!(X86Level::V3.next(), Some(X86Level::V4));
+        assert_eq!(X86Level::V4.next(), None);
+    }
+
+    #[test]
+    fn highest_usable_level() {
+        assert_eq!(data(X86Level::V3, 0x7).x86_level(), Some(X86Level::V3));
+        assert_eq!(data(X86Level::V4, 0xE7).x86_level(), Some(X86Level::V4));
+        assert_eq!(CpuData::default().x86_level(), None);
+        assert_eq!(
+            CpuData::default().missing_features(X86Level::V1),
+            X86Level::V1.features()
+        );
+    }
+
+    #[test]
+    fn missing_features_need_os_state() {
+        //AVX-512 without its XCR0 bits stops at v3
+        let no_avx512 = data(X86Level::V4, 0x7);
+        assert_eq!(no_avx512.x86_level(), Some(X86Level::V3));
+        assert_eq!(
+            no_avx512.missing_features(X86Level::V4),
+            X86Level::V4.features()
+        );
+
+        //Without XCR0 at all, CPUID support decides
+        let mut unknown = data(X86Level::V4, 0);
+        unknown.warn(CpuField::XsaveState, "no XCR0");
+        assert_eq!(unknown.x86_level(), Some(X86Level::V4));
+    }
+
+    #[test]
+    fn march_names() {
+        let names = |level: X86Level| {
+            (
+                level.rustc_target_cpu(),
+                level.gcc_march(),
+                level.clang_march(),
+            )
+        };
+        assert_eq!(names(X86Level::V1), ("x86-64", "x86-64", "x86-64"));
+        assert_eq!(names(X86Level::V3), ("x86-64-v3", "x86-64-v3", "x86-64-v3"));
+    }
+}
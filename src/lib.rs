@@ -0,0 +1,50 @@
+mod cpu_info;
+
+pub use cpu_info::{
+    AddressWidths, CacheDescriptor, CacheType, Confidence, CoreGroup, CoreType, CpuData,
+    CpuFeature, CpuFeatures, CpuField, CpuInfo, CpuSnapshot, CpuidDecoder, CpuidDump, CpuidResult,
+    CpuidSource, DumpedCpu, EpcSection, FeatureSupport, Hypervisor, HypervisorVendor, LogicalCpu,
+    MemoryEncryption, Microarchitecture, NativeCpuid, POWER_FEATURES, PageSize, ProbeError,
+    ProbeOptions, ProbeWarning, Provenance, SCHEMA_VERSION, SECURITY_FEATURES, Sgx, Signature,
+    Source, TlbDescriptor, TlbType, TopologyLevel, TopologyNode, X86Level,
+};
+
+#[cfg(target_os = "linux")]
+pub use cpu_info::LinuxCpuInfo;
+#[cfg(target_os = "windows")]
+pub use cpu_info::WindowsCpuInfo;
+
+#[cfg(not(any(target_os = "windows", target_os = "linux")))]
+compile_error!("Target OS not supported");
+
+/// Probes the CPU of the host the program is running on, using the backend
+/// matching the target OS. Panics when probing fails, see
+/// [`try_host_cpu_info`] for the fallible variant.
+pub fn host_cpu_info() -> impl CpuInfo {
+    #[cfg(target_os = "windows")]
+    return WindowsCpuInfo::new();
+    #[cfg(target_os = "linux")]
+    return LinuxCpuInfo::new();
+}
+
+/// Fallible variant of [`host_cpu_info`].
+pub fn try_host_cpu_info() -> Result<impl CpuInfo, ProbeError> {
+    #[cfg(target_os = "windows")]
+    return WindowsCpuInfo::try_new();
+    #[cfg(target_os = "linux")]
+    return LinuxCpuInfo::try_new();
+}
+
+/// Probes the host CPU with the given options, e.g. a different filesystem
+/// root or a CPUID dump instead of the live instruction.
+pub fn host_cpu_info_with(options: &ProbeOptions) -> Result<impl CpuInfo + use<>, ProbeError> {
+    #[cfg(target_os = "windows")]
+    return WindowsCpuInfo::with_options(options);
+    #[cfg(target_os = "linux")]
+    return LinuxCpuInfo::with_options(options);
+}
+
+/// Starts a [`ProbeOptions`] builder for probing only part of the host CPU.
+pub fn builder() -> ProbeOptions {
+    ProbeOptions::new()
+}
@@ -2,23 +2,32 @@ use std::io::Write;
 
 use chrono::Local;
 
-use crate::{
-    cpu_info::CpuInfo,
-    utils::{AlignString, Colors, bytes_to_string, clear_terminal_screen, time_to_string},
+use cpu_info::{
+    CpuData, CpuFeature, CpuField, CpuInfo, CpuidDump, FeatureSupport, PageSize, ProbeError,
+    ProbeOptions, TopologyLevel, TopologyNode, X86Level,
 };
 
-mod cpu_info;
-mod utils;
+use crate::utils::{AlignString, Colors, bytes_to_string, clear_terminal_screen, time_to_string};
 
-#[cfg(target_os = "windows")]
-type HostCpuInfo = crate::cpu_info::WindowsCpuInfo;
-#[cfg(target_os = "linux")]
-type HostCpuInfo = crate::cpu_info::LinuxCpuInfo;
-#[cfg(not(any(target_os = "windows", target_os = "linux")))]
-compile_error!("Target OS not supported");
+mod utils;
 
 fn main() {
-    let cpu_info = HostCpuInfo::new();
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    match args.first().map(String::as_str) {
+        Some("dump") => return dump(args.get(1)),
+        Some("level") => return level(),
+        Some("cpus") => return cpus(),
+        Some("topology") => return topology(),
+        Some(other) => {
+            eprintln!(
+                "unknown command \"{other}\"\nusage: cpu-info [dump [FILE] | level | cpus | topology]"
+            );
+            std::process::exit(2);
+        }
+        None => {}
+    }
+
+    let cpu_info = cpu_info::host_cpu_info();
 
     let mut last_width = 0;
 
@@ -30,6 +39,158 @@ fn main() {
     }
 }
 
+fn dump(path: Option<&String>) {
+    let dump = CpuidDump::capture();
+    let result = match path {
+        Some(path) => dump.save(path),
+        None => dump
+            .write_to(std::io::stdout().lock())
+            .map_err(|e| ProbeError::Io("<stdout>".into(), e)),
+    };
+    if let Err(e) = result {
+        eprintln!("failed to write CPUID dump: {e}");
+        std::process::exit(1);
+    }
+}
+
+fn level() {
+    let cpu_info = cpu_info::host_cpu_info();
+    let data = cpu_info.static_data();
+    let Some(level) = data.x86_level() else {
+        let missing = data.missing_features(X86Level::V1);
+        println!("x86-64-v1 not met, missing: {}", join_features(&missing));
+        return;
+    };
+
+    println!("{level}");
+    println!("  rustc: -C target-cpu={}", level.rustc_target_cpu());
+    println!("  gcc:   -march={}", level.gcc_march());
+    println!("  clang: -march={}", level.clang_march());
+    if let Some(next) = level.next() {
+        let missing = data.missing_features(next);
+        println!("{next} missing: {}", join_features(&missing));
+        for feature in missing {
+            let support = data.support(feature);
+            if support != FeatureSupport::Unsupported {
+                println!("  {feature} is {support}");
+            }
+        }
+    }
+}
+
+fn cpus() {
+    let cpu_info = match ProbeOptions::new().with_per_cpu_table().build() {
+        Ok(cpu_info) => cpu_info,
+        Err(e) => {
+            eprintln!("failed to probe the CPU: {e}");
+            std::process::exit(1);
+        }
+    };
+    let data = cpu_info.static_data();
+    if data.logical_cpus().is_empty() {
+        println!("per-CPU table unavailable");
+        return;
+    }
+
+    println!("CPU   APIC  Type  Signature   Microcode");
+    for cpu in data.logical_cpus() {
+        let core_type = cpu.core_type().map_or('-', |t| t.letter());
+        let signature = format!("{:X}/{:X}/{:X}", cpu.family(), cpu.model(), cpu.stepping());
+        let microcode = match cpu.microcode_version() {
+            "" => "-",
+            microcode => microcode,
+        };
+        print!(
+            "{:<5} {:<5} {:<5} {:<11} {}",
+            cpu.cpu(),
+            cpu.apic_id(),
+            core_type,
+            signature,
+            microcode
+        );
+        if cpu.differs() {
+            let odd: Vec<String> = cpu
+                .odd_flags()
+                .iter()
+                .map(|f| {
+                    let sign = if cpu.flags().has(f) { '+' } else { '-' };
+                    format!("{sign}{}", f.name())
+                })
+                .collect();
+            print!("  differs: {}", odd.join(" "));
+        }
+        println!();
+    }
+}
+
+fn topology() {
+    let cpu_info = cpu_info::host_cpu_info();
+    let data = cpu_info.static_data();
+    if data.topology().is_empty() {
+        println!("topology unavailable");
+        return;
+    }
+    for package in data.topology() {
+        print_topology_node(data, package, 0);
+    }
+}
+
+/// Prints a node and the nodes below it down to cores, with the L3 it
+/// holds. On AMD, dies are CCDs and modules CCXs.
+fn print_topology_node(data: &CpuData, node: &TopologyNode, depth: usize) {
+    let amd = matches!(data.vendor().as_str(), "AuthenticAMD" | "HygonGenuine");
+    let name = match node.level() {
+        TopologyLevel::Die if amd => String::from("CCD"),
+        TopologyLevel::Module if amd => String::from("CCX"),
+        level => level.to_string(),
+    };
+    let mut line = format!("{}{name} {}:", "  ".repeat(depth), node.id());
+    match node.level() {
+        TopologyLevel::Core => {
+            let cpus: Vec<String> = node.cpus().iter().map(usize::to_string).collect();
+            line.push_str(&format!(" cpus {}", cpus.join(",")));
+        }
+        _ => line.push_str(&format!(
+            " {} cores, {} threads",
+            node.count(TopologyLevel::Core),
+            node.count(TopologyLevel::Thread)
+        )),
+    }
+    if let Some(l3) = (node.level() != TopologyLevel::Core)
+        .then(|| node.cpus().first())
+        .flatten()
+        .and_then(|&cpu| data.cache_of(cpu, 3))
+        .filter(|l3| l3.shared_cpus() == node.cpus())
+    {
+        line.push_str(&format!(" (L3 {}B)", bytes_to_string(l3.size() as u128)));
+    }
+    println!("{line}");
+
+    if node.level() != TopologyLevel::Core {
+        print_topology_children(data, node, depth + 1);
+    }
+}
+
+/// Dies and modules that are the only one of their parent are left out.
+fn print_topology_children(data: &CpuData, node: &TopologyNode, depth: usize) {
+    for child in node.children() {
+        let only_child = node.children().len() == 1;
+        if only_child && matches!(child.level(), TopologyLevel::Die | TopologyLevel::Module) {
+            print_topology_children(data, child, depth);
+        } else {
+            print_topology_node(data, child, depth);
+        }
+    }
+}
+
+fn join_features(features: &[CpuFeature]) -> String {
+    features
+        .iter()
+        .map(|f| f.name())
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
 fn print_data<Cpu>(info: &Cpu, last_width: &mut usize)
 where
     Cpu: CpuInfo,
@@ -44,15 +205,24 @@ where
     _ = term_cursor::set_pos(0, 0);
 
     let side_panel_width = (((width - 40) / 2) - 2).max(25);
-    let mut flag_lines = vec![String::new(); 13];
-    let mut idx = 0;
-    for flag in info.static_data().flags() {
-        flag_lines[idx].push_str(format!("{}, ", flag).as_str());
-
-        if flag_lines[idx].len() >= side_panel_width - 10 {
-            idx += 1;
-        }
-    }
+    let flag_lines = wrap_flags(info.static_data(), 13, side_panel_width - 3);
+    let uarch_lines = match info.static_data().microarchitecture() {
+        Some(uarch) => [
+            format!(" Uarch:    {}", uarch.name()),
+            format!(" Codename: {}", uarch.codename()),
+            format!(" Process:  {}, {}", uarch.process(), uarch.launch_year()),
+        ],
+        None => [
+            " Uarch:    unknown".to_string(),
+            String::new(),
+            String::new(),
+        ],
+    };
+    let power_lines = power_lines(info.static_data(), side_panel_width - 2);
+    let l4_line = match info.static_data().l4_cache() {
+        0 => String::new(),
+        l4 => format!(" L4: {}B", bytes_to_string(l4 as u128)),
+    };
     println!(
         "\n{}",
         format!(
@@ -70,7 +240,9 @@ where
             format!(" Vendor:   {}", info.static_data().vendor())
                 .align_to_left(side_panel_width - 2)
                 .white(),
-            " ".align_to_center(40),
+            tlb_to_string(info.static_data(), 1)
+                .align_to_center(40)
+                .white(),
             flag_lines[0].align_to_left(side_panel_width - 3).white()
         )
         .gray()
@@ -82,7 +254,9 @@ where
             format!(" Arch:     {}", info.static_data().architecture())
                 .align_to_left(side_panel_width - 2)
                 .white(),
-            " ".align_to_center(40),
+            tlb_to_string(info.static_data(), 2)
+                .align_to_center(40)
+                .white(),
             flag_lines[1].align_to_left(side_panel_width - 3).white()
         )
         .gray()
@@ -113,18 +287,11 @@ where
                 .align_to_left(side_panel_width - 2)
                 .white(),
             format!(
-                "{}{}{}",
+                "{}┃{}┃{}",
                 " ".repeat(10),
-                format!(
-                    "┃{}┃",
-                    format!(
-                        "{}c/{}t",
-                        info.static_data().cores(),
-                        info.static_data().threads()
-                    )
+                cores_to_string(info.static_data())
                     .align_to_center(17)
-                    .white()
-                ),
+                    .white(),
                 " ".repeat(11)
             )
             .align_to_center(40),
@@ -154,19 +321,16 @@ where
         "{}",
         format!(
             " │{}│ {} │ {}│ ",
-            " ".align_to_center(side_panel_width - 2),
+            power_lines[0].align_to_left(side_panel_width - 2).white(),
             format!(
-                "{}{}{}",
+                "{}┃{}┃{}",
                 " ".repeat(10),
                 format!(
-                    "┃{}┃",
-                    format!(
-                        " L1: {}B",
-                        bytes_to_string(info.static_data().l1_cache() as u128)
-                    )
-                    .align_to_left(17)
-                    .white()
-                ),
+                    " L1: {}B",
+                    bytes_to_string(info.static_data().l1_cache() as u128)
+                )
+                .align_to_left(17)
+                .white(),
                 " ".repeat(11)
             )
             .align_to_center(40),
@@ -179,23 +343,24 @@ where
         format!(
             " │{}│ {} │ {}│ ",
             format!(
-                " Base clock speed:  {}MHz",
-                info.static_data().clock_speed()
+                " Base clock speed:  {}",
+                clock_to_string(
+                    info.static_data(),
+                    CpuField::ClockSpeed,
+                    info.static_data().clock_speed()
+                )
             )
             .align_to_left(side_panel_width - 2)
             .white(),
             format!(
-                "{}{}{}",
+                "{}┃{}┃{}",
                 " ".repeat(10),
                 format!(
-                    "┃{}┃",
-                    format!(
-                        " L2: {}B",
-                        bytes_to_string(info.static_data().l2_cache() as u128)
-                    )
-                    .align_to_left(17)
-                    .white()
-                ),
+                    " L2: {}B",
+                    bytes_to_string(info.static_data().l2_cache() as u128)
+                )
+                .align_to_left(17)
+                .white(),
                 " ".repeat(11)
             )
             .align_to_center(40),
@@ -208,23 +373,24 @@ where
         format!(
             " │{}│ {} │ {}│ ",
             format!(
-                " Turbo clock speed: {}MHz",
-                info.static_data().clock_speed_turbo()
+                " Turbo clock speed: {}",
+                clock_to_string(
+                    info.static_data(),
+                    CpuField::ClockSpeedTurbo,
+                    info.static_data().clock_speed_turbo()
+                )
             )
             .align_to_left(side_panel_width - 2)
             .white(),
             format!(
-                "{}{}{}",
+                "{}┃{}┃{}",
                 " ".repeat(10),
                 format!(
-                    "┃{}┃",
-                    format!(
-                        " L3: {}B",
-                        bytes_to_string(info.static_data().l3_cache() as u128)
-                    )
-                    .align_to_left(17)
-                    .white()
-                ),
+                    " L3: {}B",
+                    bytes_to_string(info.static_data().l3_cache() as u128)
+                )
+                .align_to_left(17)
+                .white(),
                 " ".repeat(11)
             )
             .align_to_center(40),
@@ -236,11 +402,11 @@ where
         "{}",
         format!(
             " │{}│ {} │ {}│ ",
-            " ".align_to_center(side_panel_width - 2),
+            power_lines[1].align_to_left(side_panel_width - 2).white(),
             format!(
-                "{}{}{}",
+                "{}┃{}┃{}",
                 " ".repeat(10),
-                "┃                 ┃",
+                l4_line.align_to_left(17).white(),
                 " ".repeat(11)
             )
             .align_to_center(40),
@@ -252,7 +418,7 @@ where
         "{}",
         format!(
             " │{}│ {} │ {}│ ",
-            " ".align_to_center(side_panel_width - 2),
+            uarch_lines[0].align_to_left(side_panel_width - 2).white(),
             format!(
                 "{}{}{}",
                 " ".repeat(10),
@@ -268,16 +434,13 @@ where
         "{}",
         format!(
             " │{}│ {} │ {}│ ",
-            " ".align_to_center(side_panel_width - 2),
+            uarch_lines[1].align_to_left(side_panel_width - 2).white(),
             format!(
-                "{}{}{}",
+                "{}┃{}┃{}",
                 " ".repeat(10),
-                format!(
-                    "┃{}┃",
-                    format!("{}", time_to_string(info.uptime() as u128 * 1000))
-                        .align_to_center(17)
-                        .white()
-                ),
+                time_to_string(info.uptime() as u128 * 1000)
+                    .align_to_center(17)
+                    .white(),
                 " ".repeat(11)
             )
             .align_to_center(40),
@@ -289,7 +452,7 @@ where
         "{}",
         format!(
             " │{}│ {} │ {}│ ",
-            " ".align_to_center(side_panel_width - 2),
+            uarch_lines[2].align_to_left(side_panel_width - 2).white(),
             format!(
                 "{}{}{}",
                 " ".repeat(10),
@@ -308,7 +471,9 @@ where
             format!(" Microcode:  {}", info.static_data().microcode_version())
                 .align_to_left(side_panel_width - 2)
                 .white(),
-            " ".align_to_center(40),
+            virtualized_to_string(info.static_data())
+                .align_to_center(40)
+                .yellow(),
             flag_lines[12].align_to_left(side_panel_width - 3).white()
         )
         .gray()
@@ -325,4 +490,207 @@ where
         )
         .gray()
     );
+
+    //Security panel, spanning all three columns
+    let panel_width = side_panel_width * 2 + 40;
+    println!(
+        "{}",
+        format!(
+            " ┌─{}{}┐ ",
+            " Security ".white(),
+            "─".repeat(panel_width - 11)
+        )
+        .gray()
+    );
+    for line in security_lines(info.static_data()) {
+        println!(
+            "{}",
+            format!(" │{}│ ", line.align_to_left(panel_width).white()).gray()
+        );
+    }
+    println!("{}", format!(" └{}┘ ", "─".repeat(panel_width)).gray());
+}
+
+/// "8c/16t", or "8P+16E / 32t" on hybrid CPUs.
+fn cores_to_string(data: &CpuData) -> String {
+    if data.packages() > 1 {
+        return format!("{}S/{}c/{}t", data.packages(), data.cores(), data.threads());
+    }
+    if !data.is_hybrid() {
+        return format!("{}c/{}t", data.cores(), data.threads());
+    }
+    let cores: Vec<String> = data
+        .core_groups()
+        .iter()
+        .map(|g| format!("{}{}", g.cores(), g.core_type().letter()))
+        .collect();
+    format!("{} / {}t", cores.join("+"), data.threads())
+}
+
+/// Power and clock capabilities wrapped into two lines, e.g.
+/// " Power: InvTSC Turbo HWP".
+fn power_lines(data: &CpuData, width: usize) -> [String; 2] {
+    let labels = [
+        (data.has_invariant_tsc(), "InvTSC"),
+        (data.has_turbo(), "Turbo"),
+        (data.has_hwp(), "HWP"),
+        (data.has(CpuFeature::HwpEpp), "EPP"),
+        (data.has(CpuFeature::Arat), "ARAT"),
+        (data.has(CpuFeature::Epb), "EPB"),
+        (data.has(CpuFeature::Dtherm), "DTS"),
+    ];
+    let mut lines = [String::from(" Power:"), String::new()];
+    let mut idx = 0;
+    for (_, label) in labels.iter().filter(|(has, _)| *has) {
+        if lines[idx].len() + label.len() + 1 > width {
+            if idx == 1 {
+                break;
+            }
+            idx = 1;
+        }
+        lines[idx].push(' ');
+        lines[idx].push_str(label);
+    }
+    if lines[0].len() == " Power:".len() {
+        lines[0].push_str(" none");
+    }
+    lines
+}
+
+/// Protection, speculation-control, enclave and memory-encryption
+/// capabilities, one line each.
+fn security_lines(data: &CpuData) -> [String; 4] {
+    let labels = |title: &str, labels: &[(bool, &str)]| {
+        let present: Vec<&str> = labels
+            .iter()
+            .filter(|(has, _)| *has)
+            .map(|&(_, label)| label)
+            .collect();
+        if present.is_empty() {
+            format!(" {title:<13}none")
+        } else {
+            format!(" {title:<13}{}", present.join(" "))
+        }
+    };
+
+    let protection = labels(
+        "Protection:",
+        &[
+            (data.has(CpuFeature::Smep), "SMEP"),
+            (data.has(CpuFeature::Smap), "SMAP"),
+            (data.has(CpuFeature::Umip), "UMIP"),
+            (data.has(CpuFeature::Pku), "PKU"),
+            (data.has(CpuFeature::Ospke), "OSPKE"),
+            (data.has(CpuFeature::Shstk), "CET-SS"),
+            (data.has(CpuFeature::Ibt), "CET-IBT"),
+        ],
+    );
+    let speculation = labels(
+        "Speculation:",
+        &[
+            (data.has_ibrs(), "IBRS"),
+            (data.has_ibpb(), "IBPB"),
+            (data.has_stibp(), "STIBP"),
+            (data.has_ssbd(), "SSBD"),
+            (data.has(CpuFeature::MdClear), "MD_CLEAR"),
+            (data.has(CpuFeature::FlushL1d), "L1D_FLUSH"),
+            (data.has(CpuFeature::ArchCapabilities), "ARCH_CAP"),
+        ],
+    );
+
+    let mut enclaves = labels(
+        "Enclaves:",
+        &[
+            (data.sgx().is_some_and(|sgx| sgx.sgx1()), "SGX1"),
+            (data.sgx().is_some_and(|sgx| sgx.sgx2()), "SGX2"),
+            (data.has(CpuFeature::SgxLc), "FLC"),
+            (data.has(CpuFeature::TdxGuest), "TDX guest"),
+        ],
+    );
+    if let Some(sgx) = data.sgx().filter(|sgx| sgx.epc_size() != 0) {
+        enclaves.push_str(&format!(
+            ", EPC {}B",
+            bytes_to_string(sgx.epc_size() as u128)
+        ));
+        if sgx.epc_sections().len() > 1 {
+            enclaves.push_str(&format!(" in {} sections", sgx.epc_sections().len()));
+        }
+    }
+
+    let mut encryption = labels(
+        "Encryption:",
+        &[
+            (data.has(CpuFeature::Tme), "TME"),
+            (data.has(CpuFeature::Sme), "SME"),
+            (data.has(CpuFeature::Sev), "SEV"),
+            (data.has(CpuFeature::SevEs), "SEV-ES"),
+            (data.has(CpuFeature::SevSnp), "SEV-SNP"),
+        ],
+    );
+    if let Some(encryption_info) = data.memory_encryption() {
+        encryption.push_str(&format!(", C-bit {}", encryption_info.c_bit()));
+        if encryption_info.encrypted_guests() != 0 {
+            encryption.push_str(&format!(
+                ", ASIDs {} SEV + {} SEV-ES",
+                encryption_info.sev_asids(),
+                encryption_info.sev_es_asids()
+            ));
+        }
+    }
+
+    [protection, speculation, enclaves, encryption]
+}
+
+/// "virtualized (KVM)" inside a VM, empty on bare metal.
+fn virtualized_to_string(data: &CpuData) -> String {
+    match data.hypervisor() {
+        Some(hypervisor) => format!("virtualized ({})", hypervisor.vendor()),
+        None if data.is_virtualized() => String::from("virtualized"),
+        None => String::new(),
+    }
+}
+
+/// Data TLB entries of a level per page size, e.g. "L1 dTLB  4K:64  2M:32".
+fn tlb_to_string(data: &CpuData, level: u8) -> String {
+    let entries: Vec<String> = [PageSize::Size4K, PageSize::Size2M, PageSize::Size1G]
+        .into_iter()
+        .map(|size| (size, data.tlb_entries(level, size)))
+        .filter(|&(_, entries)| entries != 0)
+        .map(|(size, entries)| format!("{size}:{entries}"))
+        .collect();
+    if entries.is_empty() {
+        return String::new();
+    }
+    format!("L{level} dTLB  {}", entries.join("  "))
+}
+
+/// Wraps flag names into `lines` lines of at most `width` characters,
+/// ending with "+N more" when they do not all fit.
+fn wrap_flags(data: &CpuData, lines: usize, width: usize) -> Vec<String> {
+    let flags: Vec<String> = data.flags().iter().map(|f| format!("{f}, ")).collect();
+    let mut flag_lines = vec![String::new(); lines];
+    let mut idx = 0;
+    for (i, flag) in flags.iter().enumerate() {
+        if flag_lines[idx].len() + flag.len() > width {
+            idx += 1;
+        }
+        let more = format!("+{} more", flags.len() - i);
+        if idx == lines
+            || (idx == lines - 1
+                && flag_lines[idx].len() + flag.len() + more.len() > width
+                && i + 1 < flags.len())
+        {
+            flag_lines[lines - 1].push_str(&more);
+            break;
+        }
+        flag_lines[idx].push_str(flag);
+    }
+    flag_lines
+}
+
+fn clock_to_string(data: &CpuData, field: CpuField, mhz: u32) -> String {
+    match data.provenance(field) {
+        Some(provenance) => format!("{mhz}MHz ({provenance})"),
+        None => String::from("unknown"),
+    }
 }
@@ -19,93 +19,93 @@ pub trait Colors {
 
 impl Colors for String {
     fn green(&self) -> Self {
-        apply_color(&self, color_config::GREEN)
+        apply_color(self, color_config::GREEN)
     }
 
     fn yellow(&self) -> Self {
-        apply_color(&self, color_config::YELLOW)
+        apply_color(self, color_config::YELLOW)
     }
 
     fn red(&self) -> Self {
-        apply_color(&self, color_config::RED)
+        apply_color(self, color_config::RED)
     }
 
     fn orange(&self) -> Self {
-        apply_color(&self, color_config::ORANGE)
+        apply_color(self, color_config::ORANGE)
     }
 
     fn bright_orange(&self) -> Self {
-        apply_color(&self, color_config::BRIGHT_ORANGE)
+        apply_color(self, color_config::BRIGHT_ORANGE)
     }
 
     fn blue(&self) -> Self {
-        apply_color(&self, color_config::BLUE)
+        apply_color(self, color_config::BLUE)
     }
 
     fn dark_blue(&self) -> Self {
-        apply_color(&self, color_config::DARK_BLUE)
+        apply_color(self, color_config::DARK_BLUE)
     }
 
     fn purple(&self) -> Self {
-        apply_color(&self, color_config::PURPLE)
+        apply_color(self, color_config::PURPLE)
     }
 
     fn black(&self) -> Self {
-        apply_color(&self, color_config::BLACK)
+        apply_color(self, color_config::BLACK)
     }
 
     fn white(&self) -> Self {
-        apply_color(&self, color_config::WHITE)
+        apply_color(self, color_config::WHITE)
     }
 
     fn gray(&self) -> Self {
-        apply_color(&self, color_config::GRAY)
+        apply_color(self, color_config::GRAY)
     }
 }
 
 impl Colors for &str {
     fn green(&self) -> String {
-        apply_color(&self, color_config::GREEN)
+        apply_color(self, color_config::GREEN)
     }
 
     fn yellow(&self) -> String {
-        apply_color(&self, color_config::YELLOW)
+        apply_color(self, color_config::YELLOW)
     }
 
     fn red(&self) -> String {
-        apply_color(&self, color_config::RED)
+        apply_color(self, color_config::RED)
     }
 
     fn orange(&self) -> String {
-        apply_color(&self, color_config::ORANGE)
+        apply_color(self, color_config::ORANGE)
     }
 
     fn bright_orange(&self) -> String {
-        apply_color(&self, color_config::BRIGHT_ORANGE)
+        apply_color(self, color_config::BRIGHT_ORANGE)
     }
 
     fn blue(&self) -> String {
-        apply_color(&self, color_config::BLUE)
+        apply_color(self, color_config::BLUE)
     }
 
     fn dark_blue(&self) -> String {
-        apply_color(&self, color_config::DARK_BLUE)
+        apply_color(self, color_config::DARK_BLUE)
     }
 
     fn purple(&self) -> String {
-        apply_color(&self, color_config::PURPLE)
+        apply_color(self, color_config::PURPLE)
     }
 
     fn black(&self) -> String {
-        apply_color(&self, color_config::BLACK)
+        apply_color(self, color_config::BLACK)
     }
 
     fn white(&self) -> String {
-        apply_color(&self, color_config::WHITE)
+        apply_color(self, color_config::WHITE)
     }
 
     fn gray(&self) -> String {
-        apply_color(&self, color_config::GRAY)
+        apply_color(self, color_config::GRAY)
     }
 }
 
@@ -116,13 +116,13 @@ pub trait CustomColor {
 
 impl CustomColor for String {
     fn custom_color(&self, color: (u8, u8, u8)) -> String {
-        apply_color(&self, color)
+        apply_color(self, color)
     }
 }
 
 impl CustomColor for &str {
     fn custom_color(&self, color: (u8, u8, u8)) -> String {
-        apply_color(&self, color)
+        apply_color(self, color)
     }
 }
 
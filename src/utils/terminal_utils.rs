@@ -100,13 +100,13 @@ pub fn number_to_string(number: u128) -> String {
         return format!("{:.2}K", number);
     }
 
-    let number = round(number as f64 / 1000.0);
+    let number = round(number / 1000.0);
 
     if number < 1000.0 {
         return format!("{:.2}M", number);
     }
 
-    format!("{:.2}B", round(number as f64 / 1000.0))
+    format!("{:.2}B", round(number / 1000.0))
 }
 
 pub fn bytes_to_string(number: u128) -> String {
@@ -120,13 +120,13 @@ pub fn bytes_to_string(number: u128) -> String {
         return format!("{:.0}K", number);
     }
 
-    let number = round(number as f64 / 1024.0);
+    let number = round(number / 1024.0);
 
     if number < 1024.0 {
         return format!("{:.0}M", number);
     }
 
-    format!("{:.0}G", round(number as f64 / 1024.0))
+    format!("{:.0}G", round(number / 1024.0))
 }
 
 fn round(number: f64) -> f64 {
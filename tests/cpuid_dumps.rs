@@ -0,0 +1,212 @@
+//dumps/ holds captures from real machines. synthetic/dumps/ is hand-written
+//from documented CPUID values, so tests on it only check the decoder against
+//those values
+
+use cpu_info::{
+    CacheType, CoreType, CpuData, CpuFeature, CpuField, CpuInfo, CpuidDump, FeatureSupport,
+    ProbeError, ProbeOptions, Source, TopologyLevel, X86Level,
+};
+
+/// Loads `tests/fixtures/<name>.txt`.
+fn load(name: &str) -> CpuidDump {
+    let path = format!("{}/tests/fixtures/{name}.txt", env!("CARGO_MANIFEST_DIR"));
+    CpuidDump::load(path).expect("fixture dump should parse")
+}
+
+/// Decodes every CPU of a dump, as `cpu-info` would on the dumped machine.
+fn replay(name: &str) -> CpuData {
+    ProbeOptions::new()
+        .with_cpuid_dump(load(name))
+        .build()
+        .expect("fixture dump should decode")
+        .static_data()
+        .clone()
+}
+
+/// Level, type, size and CPUs of every cache instance of a level.
+fn caches(data: &CpuData, level: u8) -> Vec<(CacheType, usize, Vec<usize>)> {
+    data.caches()
+        .iter()
+        .filter(|c| c.level() == level)
+        .map(|c| (c.cache_type(), c.size(), c.shared_cpus().to_vec()))
+        .collect()
+}
+
+#[test]
+fn synthetic_alder_lake_hybrid() {
+    let data = replay("synthetic/dumps/alder-lake-i3-1215u");
+    assert_eq!(data.vendor(), "GenuineIntel");
+    assert_eq!(data.brand(), "12th Gen Intel(R) Core(TM) i3-1215U");
+    assert_eq!((data.family(), data.model(), data.stepping()), (6, 0x9A, 4));
+
+    assert!(data.has(CpuFeature::Avx2) && data.has(CpuFeature::HybridCpu));
+    assert!(!data.has(CpuFeature::Avx512f));
+    assert_eq!(data.x86_level(), Some(X86Level::V3));
+
+    assert_eq!((data.cores(), data.threads(), data.packages()), (6, 8, 1));
+    let groups: Vec<(CoreType, usize, &[usize])> = data
+        .core_groups()
+        .iter()
+        .map(|g| (g.core_type(), g.cores(), g.cpus()))
+        .collect();
+    assert_eq!(
+        groups,
+        [
+            (CoreType::Performance, 2, &[0, 1, 2, 3][..]),
+            (CoreType::Efficiency, 4, &[4, 5, 6, 7][..]),
+        ]
+    );
+    let source = |field| data.provenance(field).map(|p| p.source().clone());
+    assert_eq!(
+        source(CpuField::CoreTypes),
+        Some(Source::Cpuid { leaf: 0x1A })
+    );
+    assert_eq!(
+        source(CpuField::Topology),
+        Some(Source::Cpuid { leaf: 0x1F })
+    );
+
+    //Each P-core has its own L2, the E-core module shares one
+    assert_eq!(
+        caches(&data, 2),
+        [
+            (CacheType::Unified, 1280 << 10, vec![0, 1]),
+            (CacheType::Unified, 1280 << 10, vec![2, 3]),
+            (CacheType::Unified, 2 << 20, vec![4, 5, 6, 7]),
+        ]
+    );
+    assert_eq!(data.l3_cache(), 10 << 20);
+}
+
+#[test]
+fn synthetic_zen4_ccds() {
+    let data = replay("synthetic/dumps/zen4-ryzen-9-7950x");
+    assert_eq!(data.vendor(), "AuthenticAMD");
+    assert_eq!(data.brand(), "AMD Ryzen 9 7950X 16-Core Processor");
+    assert_eq!(
+        (data.family(), data.model(), data.stepping()),
+        (0x19, 0x61, 2)
+    );
+
+    assert!(data.has(CpuFeature::Avx512f) && data.has(CpuFeature::ShaNi));
+    assert_eq!(data.x86_level(), Some(X86Level::V4));
+
+    assert_eq!((data.cores(), data.threads(), data.packages()), (16, 32, 1));
+    assert_eq!(data.topology_count(TopologyLevel::Die), 2);
+    let ccds: Vec<&[usize]> = data.topology()[0]
+        .children()
+        .iter()
+        .map(|ccd| ccd.cpus())
+        .collect();
+    assert_eq!(
+        ccds,
+        [
+            (0..16).collect::<Vec<_>>().as_slice(),
+            (16..32).collect::<Vec<_>>().as_slice(),
+        ]
+    );
+
+    //One L3 per CCD
+    let l3: Vec<(usize, Vec<usize>)> = caches(&data, 3)
+        .into_iter()
+        .map(|(_, size, cpus)| (size, cpus))
+        .collect();
+    assert_eq!(
+        l3,
+        [
+            (32 << 20, (0..16).collect()),
+            (32 << 20, (16..32).collect()),
+        ]
+    );
+    assert_eq!(
+        data.cache_of(17, 3).map(|l3| l3.shared_cpus()),
+        Some(&(16..32).collect::<Vec<_>>()[..])
+    );
+    assert_eq!(caches(&data, 2).len(), 16);
+}
+
+#[test]
+fn kvm_guest() {
+    let data = replay("dumps/kvm-sapphire-rapids");
+    assert_eq!(data.vendor(), "GenuineIntel");
+    assert_eq!((data.family(), data.model(), data.stepping()), (6, 0x8F, 8));
+
+    let hypervisor = data.hypervisor().expect("KVM should be detected");
+    assert_eq!(hypervisor.vendor().to_string(), "KVM");
+    assert!(data.is_virtualized());
+
+    assert!(data.has(CpuFeature::Avx512f) && data.has(CpuFeature::AmxTile));
+    assert_eq!(data.x86_level(), Some(X86Level::V4));
+
+    assert_eq!((data.cores(), data.threads(), data.packages()), (1, 1, 1));
+    assert_eq!(
+        caches(&data, 1),
+        [
+            (CacheType::Data, 48 << 10, vec![0]),
+            (CacheType::Instruction, 32 << 10, vec![0]),
+        ]
+    );
+}
+
+#[test]
+fn dump_without_xcr0() {
+    //cpuid -r output and v1 dumps have no XCR0, so the OS state is unknown
+    let path = format!(
+        "{}/tests/fixtures/dumps/kvm-sapphire-rapids.txt",
+        env!("CARGO_MANIFEST_DIR")
+    );
+    let text: String = std::fs::read_to_string(path)
+        .unwrap()
+        .lines()
+        .filter(|line| !line.contains("xcr0"))
+        .map(|line| format!("{line}\n"))
+        .collect();
+    let data = CpuidDump::parse(&text).unwrap().to_cpu_data().unwrap();
+
+    assert!(!data.is_known(CpuField::XsaveState));
+    assert_eq!(data.support(CpuFeature::Sse2), FeatureSupport::Usable);
+    assert_eq!(data.support(CpuFeature::Avx2), FeatureSupport::Unknown);
+    assert_eq!(data.support(CpuFeature::AmxTile), FeatureSupport::Unknown);
+    assert!(!data.is_usable(CpuFeature::Avx2));
+    assert_eq!(data.x86_level(), Some(X86Level::V4));
+    assert!(data.missing_features(X86Level::V4).is_empty());
+}
+
+#[test]
+fn dumps_round_trip() {
+    for name in [
+        "synthetic/dumps/alder-lake-i3-1215u",
+        "synthetic/dumps/zen4-ryzen-9-7950x",
+        "dumps/kvm-sapphire-rapids",
+    ] {
+        let dump = load(name);
+        let mut text = Vec::new();
+        dump.write_to(&mut text).unwrap();
+        let reparsed = CpuidDump::parse(&String::from_utf8(text).unwrap()).unwrap();
+        assert_eq!(reparsed, dump, "{name}");
+    }
+}
+
+#[test]
+fn cpuid_raw_output() {
+    //cpuid -1 -r prints a bare "CPU:" header
+    let dump = CpuidDump::parse(
+        "CPU:\n   0x00000000 0x00: eax=0x00000001 ebx=0x756e6547 ecx=0x6c65746e edx=0x49656e69\n   0x00000001 0x00: eax=0x000906ea ebx=0x00000800 ecx=0x00000000 edx=0x00000000\n",
+    )
+    .unwrap();
+    assert_eq!(dump.cpus().collect::<Vec<_>>(), [0]);
+    let data = dump.to_cpu_data().unwrap();
+    assert_eq!(
+        (data.family(), data.model(), data.stepping()),
+        (6, 0x9E, 0xA)
+    );
+}
+
+#[test]
+fn unknown_dump_version() {
+    let result = CpuidDump::parse("# cpu-info cpuid dump v3\n0 0x00000000 0x00 0x1 0x0 0x0 0x0\n");
+    assert!(matches!(
+        result,
+        Err(ProbeError::InvalidDump { line: 1, .. })
+    ));
+}
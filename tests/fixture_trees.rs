@@ -0,0 +1,129 @@
+#![cfg(target_os = "linux")]
+
+//kvm-sapphire-rapids is copied from a KVM guest. synthetic/alder-lake-i3-1215u
+//is hand-written from the part's documented topology, caches and clocks, so it
+//only checks the decoding against those values
+
+use cpu_info::{
+    CacheType, Confidence, CoreType, CpuData, CpuFeature, CpuField, CpuInfo, FeatureSupport,
+    ProbeOptions, Source, TopologyLevel, X86Level,
+};
+
+/// Decodes a captured `/proc` and `/sys` tree without running CPUID.
+fn probe(name: &str) -> CpuData {
+    ProbeOptions::new()
+        .with_root(format!(
+            "{}/tests/fixtures/{name}",
+            env!("CARGO_MANIFEST_DIR")
+        ))
+        .without_cpuid()
+        .build()
+        .expect("fixture tree should decode")
+        .static_data()
+        .clone()
+}
+
+fn source(data: &CpuData, field: CpuField) -> Option<(Source, Confidence)> {
+    data.provenance(field)
+        .map(|p| (p.source().clone(), p.confidence()))
+}
+
+#[test]
+fn synthetic_alder_lake_tree() {
+    let data = probe("synthetic/alder-lake-i3-1215u");
+    assert_eq!(data.vendor(), "GenuineIntel");
+    assert_eq!(data.brand(), "12th Gen Intel(R) Core(TM) i3-1215U");
+    assert_eq!((data.family(), data.model(), data.stepping()), (6, 0x9A, 4));
+    assert_eq!(data.microcode_version(), "0x0000042C");
+
+    //XCR0 inferred from the flags the kernel left visible
+    assert_eq!(data.enabled_state(), 0x207);
+    assert_eq!(
+        source(&data, CpuField::XsaveState),
+        Some((Source::ProcCpuinfo("flags".into()), Confidence::Medium))
+    );
+    assert_eq!(data.support(CpuFeature::Avx2), FeatureSupport::Usable);
+    assert_eq!(data.x86_level(), Some(X86Level::V3));
+
+    assert_eq!((data.cores(), data.threads(), data.packages()), (6, 8, 1));
+    assert_eq!(data.topology_count(TopologyLevel::Module), 3);
+    let groups: Vec<(CoreType, usize, u32, u32)> = data
+        .core_groups()
+        .iter()
+        .map(|g| {
+            (
+                g.core_type(),
+                g.cores(),
+                g.clock_speed(),
+                g.clock_speed_turbo(),
+            )
+        })
+        .collect();
+    assert_eq!(
+        groups,
+        [
+            (CoreType::Performance, 2, 1200, 4400),
+            (CoreType::Efficiency, 4, 900, 3300),
+        ]
+    );
+    assert_eq!(
+        source(&data, CpuField::CoreTypes),
+        Some((Source::Sysfs("cpu_core/cpus".into()), Confidence::High))
+    );
+
+    let l2: Vec<(usize, &[usize])> = data
+        .caches()
+        .iter()
+        .filter(|c| c.level() == 2)
+        .map(|c| (c.size(), c.shared_cpus()))
+        .collect();
+    assert_eq!(
+        l2,
+        [
+            (1280 << 10, &[0, 1][..]),
+            (1280 << 10, &[2, 3][..]),
+            (2 << 20, &[4, 5, 6, 7][..]),
+        ]
+    );
+    assert_eq!(data.l3_cache(), 10 << 20);
+
+    assert_eq!((data.clock_speed(), data.clock_speed_turbo()), (1200, 4400));
+    assert!(!data.is_known(CpuField::TscFrequency));
+}
+
+#[test]
+fn kvm_tree() {
+    let data = probe("kvm-sapphire-rapids");
+    assert_eq!(data.vendor(), "GenuineIntel");
+    assert_eq!((data.family(), data.model(), data.stepping()), (6, 0x8F, 8));
+    assert!(data.has(CpuFeature::Hypervisor));
+    assert!(!data.is_known(CpuField::Hypervisor));
+
+    assert_eq!(data.enabled_state(), 0x602E7);
+    assert_eq!(data.support(CpuFeature::AmxTile), FeatureSupport::Usable);
+    assert_eq!(data.x86_level(), Some(X86Level::V4));
+
+    assert_eq!((data.cores(), data.threads()), (1, 1));
+    assert!(!data.is_hybrid());
+    let caches: Vec<(u8, CacheType, usize)> = data
+        .caches()
+        .iter()
+        .map(|c| (c.level(), c.cache_type(), c.size()))
+        .collect();
+    assert_eq!(
+        caches,
+        [
+            (1, CacheType::Data, 48 << 10),
+            (1, CacheType::Instruction, 32 << 10),
+            (2, CacheType::Unified, 2 << 20),
+            (3, CacheType::Unified, 105 << 20),
+        ]
+    );
+
+    //No cpufreq in the guest, only /proc/cpuinfo's current clock
+    assert_eq!(
+        source(&data, CpuField::ClockSpeed),
+        Some((Source::ProcCpuinfo("cpu MHz".into()), Confidence::Low))
+    );
+    assert!(!data.is_known(CpuField::ClockSpeedTurbo));
+}
@@ -0,0 +1,63 @@
+#![cfg(feature = "serde")]
+
+use cpu_info::{
+    CpuFeature, CpuFeatures, CpuField, CpuInfo, CpuSnapshot, CpuidDump, ProbeOptions,
+    SCHEMA_VERSION,
+};
+
+fn snapshot() -> CpuSnapshot {
+    let path = format!(
+        "{}/tests/fixtures/dumps/kvm-sapphire-rapids.txt",
+        env!("CARGO_MANIFEST_DIR")
+    );
+    let dump = CpuidDump::load(path).unwrap();
+    let cpu = ProbeOptions::new().with_cpuid_dump(dump).build().unwrap();
+    CpuSnapshot::new(cpu.static_data().clone())
+}
+
+#[test]
+fn snapshot_round_trip() {
+    let snapshot = snapshot();
+    let mut json = serde_json::to_value(&snapshot).unwrap();
+    assert_eq!(json["schema_version"], SCHEMA_VERSION);
+
+    //Flags are stored by name, and names from newer versions are skipped
+    let flags = json["data"]["flags"].as_array_mut().unwrap();
+    assert!(flags.contains(&"avx512f".into()));
+    flags.push("flag_from_the_future".into());
+
+    let loaded: CpuSnapshot = serde_json::from_value(json).unwrap();
+    assert!(loaded.is_current());
+    let (data, loaded) = (snapshot.data(), loaded.data());
+    assert_eq!(loaded.flags(), data.flags());
+    assert_eq!(loaded.brand(), data.brand());
+    assert_eq!(loaded.caches(), data.caches());
+    assert_eq!(loaded.topology(), data.topology());
+    assert_eq!(loaded.hypervisor(), data.hypervisor());
+    assert_eq!(
+        loaded.provenance(CpuField::Flags),
+        data.provenance(CpuField::Flags)
+    );
+}
+
+#[test]
+fn older_snapshots_load_with_defaults() {
+    let loaded: CpuSnapshot = serde_json::from_str(
+        r#"{"schema_version": 2, "data": {"vendor": "GenuineIntel", "flags": ["sse2", "nx"]}}"#,
+    )
+    .unwrap();
+    assert!(!loaded.is_current());
+    assert_eq!(loaded.data().vendor(), "GenuineIntel");
+    assert!(loaded.data().caches().is_empty());
+    assert_eq!(
+        *loaded.data().flags(),
+        CpuFeatures::from_iter([CpuFeature::Sse2, CpuFeature::Nx])
+    );
+}
+
+#[test]
+fn single_features_reject_unknown_names() {
+    let avx2: CpuFeature = serde_json::from_str(r#""avx2""#).unwrap();
+    assert_eq!(avx2, CpuFeature::Avx2);
+    assert!(serde_json::from_str::<CpuFeature>(r#""flag_from_the_future""#).is_err());
+}